@@ -0,0 +1,118 @@
+use crate::{logging, swap, SERVER_ADDRESS};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::net::TcpStream;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// The name the server binary is installed under
+pub const SERVER_PROGRAM: &str = "nlo_text_editor_server";
+/// How long ensure_server_running waits for a newly started server to accept connections
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+
+/// Where the process id of the running server is recorded
+pub fn pid_file_path() -> PathBuf{
+    swap::state_dir().join("server.pid")
+}
+
+/// Where a daemonized server writes its output
+pub fn log_file_path() -> PathBuf{
+    swap::state_dir().join("server.log")
+}
+
+/// The process id of an already running server, if its pid file names a live process
+pub fn running_instance() -> Option<i32>{
+    let pid: i32 = fs::read_to_string(pid_file_path()).ok()?.trim().parse().ok()?;
+    if pid <= 0{return None;}
+
+    // signal 0 only checks that the process exists. EPERM means it exists, but belongs to someone else
+    let alive = unsafe{libc::kill(pid, 0)} == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    if alive{Some(pid)}else{None}
+}
+
+pub fn write_pid_file() -> io::Result<()>{
+    let pid_file_path = pid_file_path();
+    if let Some(parent) = pid_file_path.parent(){
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(pid_file_path, std::process::id().to_string())
+}
+
+/// Removes the pid file, if it still names this process
+pub fn remove_pid_file(){
+    let pid_file_path = pid_file_path();
+    if let Ok(contents) = fs::read_to_string(&pid_file_path){
+        if contents.trim() == std::process::id().to_string(){
+            let _ = fs::remove_file(pid_file_path);
+        }
+    }
+}
+
+/// Detaches from the controlling terminal and continues in the background, with output appended to log_path.
+/// the original process exits. must be called before any threads are spawned, because only the calling thread survives a fork.
+/// the working directory is kept, so relative paths from clients resolve the same way they would in the foreground
+pub fn daemonize(log_path: &Path) -> io::Result<()>{
+    if let Some(parent) = log_path.parent(){
+        fs::create_dir_all(parent)?;
+    }
+    let log_file = OpenOptions::new().create(true).append(true).mode(logging::LOG_FILE_MODE).open(log_path)?;
+    let dev_null = File::open("/dev/null")?;
+
+    fork_and_exit_parent()?;
+    if unsafe{libc::setsid()} == -1{
+        return Err(io::Error::last_os_error());
+    }
+    // forking again leaves a process that is not a session leader, so it can never acquire a controlling terminal
+    fork_and_exit_parent()?;
+
+    unsafe{
+        if libc::dup2(dev_null.as_raw_fd(), libc::STDIN_FILENO) == -1
+        || libc::dup2(log_file.as_raw_fd(), libc::STDOUT_FILENO) == -1
+        || libc::dup2(log_file.as_raw_fd(), libc::STDERR_FILENO) == -1{
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> io::Result<()>{
+    match unsafe{libc::fork()}{
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
+
+/// For clients. connects to the server, starting it as a daemon first if it is not already running.
+/// the server binary is looked up on PATH, where cargo install puts it
+pub fn ensure_server_running() -> io::Result<TcpStream>{
+    if let Ok(stream) = TcpStream::connect(SERVER_ADDRESS){
+        return Ok(stream);
+    }
+
+    let status = Command::new(SERVER_PROGRAM)
+        .arg("--daemon")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success(){
+        return Err(io::Error::other(format!("{} --daemon exited with {}", SERVER_PROGRAM, status)));
+    }
+
+    // the server listens before it detaches, but retry in case another instance was mid start up
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop{
+        match TcpStream::connect(SERVER_ADDRESS){
+            Ok(stream) => return Ok(stream),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
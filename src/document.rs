@@ -1,15 +1,25 @@
 use crate::{Position, View};
 use crate::selection::{Selection, Selection2d};
 use crate::movement;
+use crate::encoding::{self, Encoding};
+use crate::swap::{self, SwapFile};
+use crate::session::DocumentState;
+use crate::indent::{self, Indent, IndentStyle};
+use crate::language::{self, Language};
+use crate::large_file::{LargeFile, LARGE_FILE_THRESHOLD};
+use crate::error::EditorError;
+use std::collections::BTreeSet;
+use std::ops::Range;
 use std::fs::{self, File};
-use std::error::Error;
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
-use unicode_segmentation::UnicodeSegmentation;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use ropey::{Rope, RopeSlice};
 
-// tab keypress inserts the number of spaces specified in TAB_WIDTH into the focused document
-pub const TAB_WIDTH: usize = 4;
+/// The brackets and quotes auto pairing closes. quotes pair with themselves
+const AUTO_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')];
+/// The brackets MatchBracket jumps between
+const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
 
 
 
@@ -17,6 +27,25 @@ pub struct Document{
     text: Rope,
     file_name: Option<String>,
     modified: bool,
+    /// The encoding the text was decoded from, and will be encoded to on save
+    encoding: Encoding,
+    /// Whether the file began with a byte order mark, which is written back on save
+    has_bom: bool,
+    /// Where unsaved state is persisted, so it can be recovered if the server dies. None for unnamed and large files
+    swap_path: Option<PathBuf>,
+    /// A swap file from a previous server run exists for this document, and the client has not yet chosen to recover or discard it
+    recovery_available: bool,
+    /// Edits and saves are refused. set when the file is not writable, or when the client asks for it
+    read_only: bool,
+    /// Set for files too large to read into text. the document is then read only, lines are read from disk as the view 
+    /// needs them, and cursor movement is disabled
+    large_file: Option<LargeFile>,
+    /// What a tab keypress inserts, and how wide tab characters are displayed. detected from the text at open
+    indent: Indent,
+    /// Detected from the file extension at open. decides the comment syntax ToggleComment uses
+    language: Option<&'static Language>,
+    /// Whether typing an opening bracket or quote also inserts its closer. on by default
+    auto_pairs: bool,
     /// must have at least one selection at all times
     selections: Vec<Selection>, //Selections
     /// The dimensions of the area a client has for displaying this document
@@ -28,25 +57,97 @@ impl Default for Document{
             text: Rope::new(),
             file_name: None,
             modified: false,
+            encoding: Encoding::default(),
+            has_bom: false,
+            swap_path: None,
+            recovery_available: false,
+            read_only: false,
+            large_file: None,
+            indent: Indent::default(),
+            language: None,
+            auto_pairs: true,
             selections: vec![Selection::default()],
             client_view: View::default(),
         }
     }
 }
 impl Document{
-    pub fn open(path: &PathBuf) -> Result<Self, Box<dyn Error>>{
-        let text = Rope::from_reader(BufReader::new(File::open(path)?))?;
+    /// Opens the file at path. binary files are refused with EditorError::FileIsBinary, unless force is set. 
+    /// the swap file is kept under state_dir
+    pub fn open(path: &PathBuf, read_only: bool, force: bool, state_dir: &Path) -> Result<Self, EditorError>{
+        let metadata = fs::metadata(path).map_err(|e| EditorError::io(path, e))?;
+        if metadata.len() >= LARGE_FILE_THRESHOLD{
+            return Document::open_large_file(path, force);
+        }
+
+        let bytes = fs::read(path).map_err(|e| EditorError::io(path, e))?;
+        if !force && encoding::is_binary(&bytes){
+            return Err(EditorError::FileIsBinary{path: path.clone()});
+        }
+        // opening for append neither truncates nor modifies the file, but fails the same way a save would without write permission
+        let writable = fs::OpenOptions::new().append(true).open(path).is_ok();
+        let (encoding, has_bom) = encoding::detect(&bytes);
+        let text = Rope::from(encoding::decode(&bytes, encoding, has_bom)?);
+        let indent = indent::detect(text.slice(..)).unwrap_or_default();
+        let swap_path = swap::swap_path(state_dir, path);
     
         Ok(Self{
             text,
             file_name: Some(path.to_string_lossy().to_string()),
             modified: false,
+            encoding,
+            has_bom,
+            recovery_available: swap_path.exists(),
+            swap_path: Some(swap_path),
+            read_only: read_only || !writable,
+            large_file: None,
+            indent,
+            language: language::from_path(path),
+            auto_pairs: true,
             selections: vec![Selection::default()],
             client_view: View::default(),
         })
     }
 
-    pub fn text(&self) -> RopeSlice{
+    // only the first chunk is read up front. it decides the encoding, and whether the file is binary
+    fn open_large_file(path: &PathBuf, force: bool) -> Result<Self, EditorError>{
+        let mut first_chunk = Vec::new();
+        File::open(path)
+            .and_then(|file| file.take(encoding::BINARY_SNIFF_LENGTH as u64).read_to_end(&mut first_chunk))
+            .map_err(|e| EditorError::io(path, e))?;
+        if !force && encoding::is_binary(&first_chunk){
+            return Err(EditorError::FileIsBinary{path: path.clone()});
+        }
+        // the chunk boundary may split a multi byte sequence, which should not make valid utf8 look like latin1
+        if let Err(e) = std::str::from_utf8(&first_chunk){
+            if e.error_len().is_none(){
+                first_chunk.truncate(e.valid_up_to());
+            }
+        }
+        let (encoding, has_bom) = encoding::detect(&first_chunk);
+        if matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be){
+            return Err(EditorError::LargeFileEncodingUnsupported{path: path.clone(), encoding});
+        }
+
+        Ok(Self{
+            text: Rope::new(),
+            file_name: Some(path.to_string_lossy().to_string()),
+            modified: false,
+            encoding,
+            has_bom,
+            swap_path: None,
+            recovery_available: false,
+            read_only: true,
+            large_file: Some(LargeFile::open(path, encoding, has_bom).map_err(|e| EditorError::io(path, e))?),
+            indent: Indent::default(),
+            language: language::from_path(path),
+            auto_pairs: true,
+            selections: vec![Selection::default()],
+            client_view: View::default(),
+        })
+    }
+
+    pub fn text(&self) -> RopeSlice<'_>{
         self.text.slice(..)
     }
 
@@ -54,6 +155,60 @@ impl Document{
         self.file_name.clone()
     }
 
+    pub fn is_large_file(&self) -> bool{
+        self.large_file.is_some()
+    }
+
+    pub fn len_lines(&self) -> usize{
+        match &self.large_file{
+            Some(large_file) => large_file.len_lines(),
+            None => self.text.len_lines(),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool{
+        self.read_only
+    }
+
+    pub fn encoding(&self) -> Encoding{
+        self.encoding
+    }
+
+    pub fn has_bom(&self) -> bool{
+        self.has_bom
+    }
+
+    pub fn indent(&self) -> Indent{
+        self.indent
+    }
+
+    pub fn language(&self) -> Option<&'static Language>{
+        self.language
+    }
+
+    /// Changes what a tab keypress inserts, and how wide tab characters are displayed. a width of 0 is treated as 1
+    pub fn set_indent(&mut self, style: IndentStyle, width: usize){
+        self.indent = Indent{style, width: width.max(1)};
+    }
+
+    /// Turns auto pairing of brackets and quotes on or off
+    pub fn set_auto_pairs(&mut self, enabled: bool){
+        self.auto_pairs = enabled;
+    }
+
+    /// Changes the encoding the document will be written with on its next save. fails, without changing anything, if the current text can not be represented in the requested encoding
+    pub fn set_encoding(&mut self, encoding: Encoding, has_bom: bool) -> Result<(), EditorError>{
+        encoding::encode(self.text.slice(..), encoding, has_bom)?;
+
+        if encoding != self.encoding || has_bom != self.has_bom{
+            self.encoding = encoding;
+            self.has_bom = has_bom;
+            self.modified = true;
+        }
+
+        Ok(())
+    }
+
     /// Translates a 1 dimensional rope cursor to a 2 dimensional document cursor
     fn rope_cursor_position_to_document_cursor_position(rope_cursor: Selection, text: RopeSlice) -> Selection2d{
         let line_number_head = text.char_to_line(rope_cursor.head());
@@ -111,50 +266,56 @@ impl Document{
     //
     //}
 
-    //currently using insert_char('\n') for enter. not currently handling auto indent
-            //pub fn enter(&mut self){        
-            //    for cursor in self.cursors.iter_mut(){
-            //        Document::enter_at_cursor(cursor, &mut self.lines, &mut self.modified);
-            //    }
-            //}
-            // auto indent doesn't work correctly if previous line has only whitespace characters
-            // also doesn't auto indent for first line of function bodies, because function declaration
-            // is at lower indentation level
-            //fn enter_at_cursor(cursor: &mut Cursor, lines: &mut Vec<String>, modified: &mut bool){
-            //    *modified = true;
-            //    
-            //    match lines.get_mut(cursor.head.y){
-            //        Some(line) => {
-            //            let start_of_line = get_first_non_whitespace_character_index(line);
-            //            let mut modified_current_line: String = String::new();
-            //            let mut new_line: String = String::new();
-            //            for (index, grapheme) in line[..].graphemes(true).enumerate(){
-            //                if index < cursor.head.x{
-            //                    modified_current_line.push_str(grapheme);
-            //                }
-            //                else{
-            //                    new_line.push_str(grapheme);
-            //                }
-            //            }
-            //            *line = modified_current_line;
-            //            lines.insert(cursor.head.y.saturating_add(1), new_line);
-            //            Document::move_cursor_right(cursor, &lines);
-            //            // auto indent
-            //            if start_of_line != 0{
-            //                for _ in 0..start_of_line{
-            //                    Document::insert_char_at_cursor(' ', cursor, lines, modified);
-            //                }
-            //            }
-            //        }
-            //        None => panic!("No line at cursor position. This should be impossible")
-            //    }
-            //}
+    /// Inserts a newline at each cursor, indented to match the cursor's line. a line that opens a block, by ending in an 
+    /// opening bracket or colon before the cursor, indents the new line one level further
+    pub fn enter(&mut self){
+        self.modified = true;
+
+        let indent_unit = self.indent.unit();
+        for index in self.selections_bottom_up(){
+            self.insert_at_selection(index, |selection, text| Document::enter_at_cursor(selection, text, &indent_unit));
+        }
+    }
+    fn enter_at_cursor(mut selection: Selection, text: RopeSlice, indent_unit: &str) -> (Selection, Rope){
+        let line_start = text.line_to_char(text.char_to_line(selection.head()));
+        // only what precedes the cursor counts. text after it moves to the new line
+        let before_cursor = text.slice(line_start..selection.head());
+        let indentation_width = get_first_non_whitespace_character_index(before_cursor);
+        let mut inserted = String::from("\n");
+        inserted.extend(before_cursor.slice(..indentation_width).chars());
+        if opens_block(before_cursor){
+            inserted.push_str(indent_unit);
+        }
 
+        let mut new_text = Rope::from(text);
+        new_text.insert(selection.head(), &inserted);
+        for _ in inserted.chars(){
+            selection = movement::move_cursor_right(selection, new_text.slice(..));
+        }
+
+        (selection, new_text)
+    }
+
+    /// Inserts c at each cursor. with auto pairs on, an opening bracket or quote also inserts its closer after the cursor, 
+    /// unless the cursor is inside a word, and typing a closer that is already next to the cursor steps over it instead
     pub fn insert_char(&mut self, c: char){
-        self.modified = true;
-        
-        for cursor in self.selections.iter_mut(){
-            (*cursor, self.text) = Document::insert_char_at_cursor(cursor.clone(), self.text.slice(..), c);
+        for index in self.selections_bottom_up(){
+            let head = self.selections[index].head();
+            let next = self.text.get_char(head);
+            if self.auto_pairs && next == Some(c) && AUTO_PAIRS.iter().any(|(_, close)| *close == c){
+                self.selections[index] = movement::move_cursor_right(self.selections[index].clone(), self.text.slice(..));
+                continue;
+            }
+            let previous = head.checked_sub(1).and_then(|index| self.text.get_char(index));
+            let close = auto_pair_closer(c, previous, next).filter(|_| self.auto_pairs);
+            self.insert_at_selection(index, |selection, text| {
+                let (selection, mut text) = Document::insert_char_at_cursor(selection, text, c);
+                if let Some(close) = close{
+                    text.insert_char(selection.head(), close);
+                }
+                (selection, text)
+            });
+            self.modified = true;
         }
     }
     fn insert_char_at_cursor(mut selection: Selection, text: RopeSlice, char: char) -> (Selection, Rope){
@@ -165,19 +326,446 @@ impl Document{
         (selection, new_text)
     }
 
+    /// Inserts one indent at each cursor. if a selection spans more than one line, every selected line is indented instead
     pub fn tab(&mut self){
+        if self.selections.iter().any(|selection| {
+            self.text.char_to_line(selection.head()) != self.text.char_to_line(selection.anchor())
+        }){
+            self.indent_lines();
+            return;
+        }
         self.modified = true;
 
-        for selection in self.selections.iter_mut(){
-            let tab_distance = distance_to_next_multiple_of_tab_width(selection.clone());
-            let modified_tab_width = if tab_distance > 0 && tab_distance < TAB_WIDTH{
-                tab_distance
+        for index in self.selections_bottom_up(){
+            let head = self.selections[index].head();
+            let inserted = match self.indent.style{
+                IndentStyle::Tabs => String::from("\t"),
+                // pads to the next indent stop
+                IndentStyle::Spaces => {
+                    let line_start = self.text.line_to_char(self.text.char_to_line(head));
+                    let column = indent::display_column(self.text.slice(line_start..head).chars(), usize::MAX, self.indent.width);
+                    " ".repeat(self.indent.width - (column % self.indent.width))
+                }
+            };
+            self.insert_at_selection(index, |mut selection, text| {
+                let mut text = Rope::from(text);
+                for char in inserted.chars(){
+                    (selection, text) = Document::insert_char_at_cursor(selection, text.slice(..), char);
+                }
+                (selection, text)
+            });
+        }
+    }
+
+    /// Indents every line touched by a selection by one indent unit. lines of only whitespace are left alone. 
+    /// selections stay on the same text. each line is indented once, even if more than one selection touches it
+    pub fn indent_lines(&mut self){
+        let unit = self.indent.unit();
+        let unit_length = unit.chars().count();
+        // from the bottom up, so earlier line starts are unaffected by each insert
+        for line_number in self.selected_lines().into_iter().rev(){
+            let line = self.text.line(line_number);
+            if get_first_non_whitespace_character_index(line) == line_width_excluding_newline(line){continue;}
+            let line_start = self.text.line_to_char(line_number);
+            self.text.insert(line_start, &unit);
+            shift_selections_after_insert(&mut self.selections, line_start, unit_length);
+            self.modified = true;
+        }
+        self.update_stored_line_positions();
+    }
+
+    /// Removes up to one indent unit of leading whitespace from every line touched by a selection. space indentation is
+    /// reduced to the previous indent stop. selections stay on the same text
+    pub fn dedent_lines(&mut self){
+        for line_number in self.selected_lines().into_iter().rev(){
+            let line = self.text.line(line_number);
+            let remove = if line.len_chars() > 0 && line.char(0) == '\t'{
+                1
             }else{
-                TAB_WIDTH
+                let spaces = line.chars().take_while(|char| *char == ' ').count();
+                match spaces % self.indent.width{
+                    0 => spaces.min(self.indent.width),
+                    partial => partial,
+                }
             };
-            for _ in 0..modified_tab_width{
-                (*selection, self.text) = Document::insert_char_at_cursor(selection.clone(), self.text.slice(..), ' ');
+            if remove == 0{continue;}
+            let line_start = self.text.line_to_char(line_number);
+            self.text.remove(line_start..line_start + remove);
+            shift_selections_after_remove(&mut self.selections, line_start, remove);
+            self.modified = true;
+        }
+        self.update_stored_line_positions();
+    }
+
+    /// Comments out every non blank line a selection touches, with the language's line comment token placed at the 
+    /// smallest indentation among them, or removes the comments if every one of those lines is already commented. 
+    /// languages without line comments wrap each line in a block comment instead. does nothing when the language is unknown
+    pub fn toggle_comment(&mut self){
+        let Some(language) = self.language else{return;};
+        let (open, close) = match (language.line_comment, language.block_comment){
+            (Some(token), _) => (token, None),
+            (None, Some((open, close))) => (open, Some(close)),
+            (None, None) => return,
+        };
+        let lines: Vec<usize> = self.selected_lines().into_iter().filter(|line_number| {
+            let line = self.text.line(*line_number);
+            get_first_non_whitespace_character_index(line) < line_width_excluding_newline(line)
+        }).collect();
+        if lines.is_empty(){return;}
+
+        let is_commented = |line: String| {
+            let trimmed = line.trim();
+            trimmed.starts_with(open) && close.is_none_or(|close| trimmed.len() >= open.len() + close.len() && trimmed.ends_with(close))
+        };
+        let uncomment = lines.iter().all(|line_number| is_commented(self.text.line(*line_number).to_string()));
+        let min_indentation = lines.iter().map(|line_number| get_first_non_whitespace_character_index(self.text.line(*line_number))).min().unwrap_or(0);
+        let open_length = open.chars().count();
+        // from the bottom up, and the close token before the open token on each line, so pending positions are unaffected
+        for line_number in lines.into_iter().rev(){
+            let line_start = self.text.line_to_char(line_number);
+            let line = self.text.line(line_number);
+            let indentation = get_first_non_whitespace_character_index(line);
+            let width = line_width_excluding_newline(line);
+            let content_end = line_start + width - (0..width).rev().take_while(|index| line.char(*index).is_whitespace()).count();
+            if uncomment{
+                if let Some(close) = close{
+                    let mut close_start = content_end - close.chars().count();
+                    if close_start > line_start + indentation + open_length && self.text.char(close_start - 1) == ' '{
+                        close_start -= 1;
+                    }
+                    self.text.remove(close_start..content_end);
+                    shift_selections_after_remove(&mut self.selections, close_start, content_end - close_start);
+                }
+                let open_start = line_start + indentation;
+                let mut open_end = open_start + open_length;
+                if open_end < self.text.len_chars() && self.text.char(open_end) == ' '{
+                    open_end += 1;
+                }
+                self.text.remove(open_start..open_end);
+                shift_selections_after_remove(&mut self.selections, open_start, open_end - open_start);
+            }else{
+                if let Some(close) = close{
+                    let inserted = format!(" {}", close);
+                    self.text.insert(content_end, &inserted);
+                    // a cursor at the end of the content stays inside the comment
+                    shift_selections_after_insert(&mut self.selections, content_end + 1, inserted.chars().count());
+                }
+                let inserted = format!("{} ", open);
+                self.text.insert(line_start + min_indentation, &inserted);
+                shift_selections_after_insert(&mut self.selections, line_start + min_indentation, inserted.chars().count());
+            }
+            self.modified = true;
+        }
+        self.update_stored_line_positions();
+    }
+
+    /// Wraps each selection in open and close. selections stay on the text they wrapped
+    pub fn surround(&mut self, open: char, close: char){
+        // from the last selection back, so inserts leave the positions of those still to be handled alone
+        let mut order: Vec<usize> = (0..self.selections.len()).collect();
+        order.sort_by_key(|index| std::cmp::Reverse(self.selections[*index].head().min(self.selections[*index].anchor())));
+        for index in order{
+            let selection = self.selections[index].clone();
+            let start = selection.head().min(selection.anchor());
+            let end = selection.head().max(selection.anchor());
+            self.text.insert_char(end, close);
+            shift_selections_after_insert(&mut self.selections, end, 1);
+            self.text.insert_char(start, open);
+            shift_selections_after_insert(&mut self.selections, start, 1);
+            self.selections[index] = Selection::new(selection.anchor() + 1, selection.head() + 1, 0);
+            self.modified = true;
+        }
+        self.update_stored_line_positions();
+    }
+
+    /// Removes the nearest pair of char, or of the bracket it is one half of, enclosing each selection. selections 
+    /// without an enclosing pair are left alone, and stay on the same text
+    pub fn delete_surround(&mut self, char: char){
+        let (open, close) = surround_pair(char);
+        let mut positions = BTreeSet::new();
+        for selection in self.selections.iter(){
+            let start = selection.head().min(selection.anchor());
+            let end = selection.head().max(selection.anchor());
+            if let Some((open_position, close_position)) = find_enclosing_pair(self.text.slice(..), start, end, open, close){
+                positions.insert(open_position);
+                positions.insert(close_position);
+            }
+        }
+        for position in positions.into_iter().rev(){
+            self.text.remove(position..position + 1);
+            shift_selections_after_remove(&mut self.selections, position, 1);
+            self.modified = true;
+        }
+        self.update_stored_line_positions();
+    }
+
+    /// Replaces the nearest pair of from enclosing each selection with the pair of to. from and to may each be either 
+    /// half of a bracket pair. any other char pairs with itself
+    pub fn change_surround(&mut self, from: char, to: char){
+        let (from_open, from_close) = surround_pair(from);
+        let (to_open, to_close) = surround_pair(to);
+        let pairs: Vec<(usize, usize)> = self.selections.iter().filter_map(|selection| {
+            let start = selection.head().min(selection.anchor());
+            let end = selection.head().max(selection.anchor());
+            find_enclosing_pair(self.text.slice(..), start, end, from_open, from_close)
+        }).collect();
+        // one char replaces another, so no position moves
+        for (open_position, close_position) in pairs{
+            self.text.remove(open_position..open_position + 1);
+            self.text.insert_char(open_position, to_open);
+            self.text.remove(close_position..close_position + 1);
+            self.text.insert_char(close_position, to_close);
+            self.modified = true;
+        }
+    }
+
+    /// Moves each cursor on a bracket to its match. with extend set, only the selection head moves. cursors not on a 
+    /// bracket, or on one without a match, stay put
+    pub fn match_bracket(&mut self, extend: bool){
+        for index in 0..self.selections.len(){
+            let Some((_, partner)) = self.bracket_pair(self.selections[index].head(), 0..self.text.len_lines()) else{continue;};
+            let selection = &mut self.selections[index];
+            if extend{
+                selection.set_head(partner);
+            }else{
+                *selection = Selection::new(partner, partner, 0);
+            }
+        }
+        self.update_stored_line_positions();
+    }
+
+    // the position of the bracket a cursor at head is on, and of its match. a cursor just after a bracket is on it too,
+    // unless it is also just before one. brackets in strings and comments are ignored when the language is known. only 
+    // lines is searched for the match
+    fn bracket_pair(&self, head: usize, lines: Range<usize>) -> Option<(usize, usize)>{
+        let is_bracket = |position: usize| {
+            self.text.get_char(position).is_some_and(|char| BRACKETS.iter().any(|(open, close)| char == *open || char == *close))
+        };
+        let position = if is_bracket(head){
+            head
+        }else if head > 0 && is_bracket(head - 1){
+            head - 1
+        }else{
+            return None;
+        };
+
+        find_matching_bracket(self.text.slice(..), position, self.language, lines).map(|partner| (position, partner))
+    }
+
+    // selection indices from the last cursor in the text to the first, so each edit leaves the positions of the cursors 
+    // still to be handled alone
+    fn selections_bottom_up(&self) -> Vec<usize>{
+        let mut order: Vec<usize> = (0..self.selections.len()).collect();
+        order.sort_by_key(|index| std::cmp::Reverse(self.selections[*index].head()));
+        order
+    }
+
+    // applies an edit inserting text at the head of the selection at index, and moves the other selections past it
+    fn insert_at_selection(&mut self, index: usize, edit: impl FnOnce(Selection, RopeSlice) -> (Selection, Rope)){
+        let head = self.selections[index].head();
+        let length = self.text.len_chars();
+        let (selection, text) = edit(self.selections[index].clone(), self.text.slice(..));
+        self.text = text;
+        shift_selections_after_insert(&mut self.selections, head, self.text.len_chars() - length);
+        self.selections[index] = selection;
+    }
+
+    // every line touched by a selection, in order, without duplicates. a selection ending at the very start of a line 
+    // does not include that line
+    fn selected_lines(&self) -> BTreeSet<usize>{
+        let mut lines = BTreeSet::new();
+        for selection in &self.selections{
+            let start = selection.head().min(selection.anchor());
+            let end = selection.head().max(selection.anchor());
+            let first_line = self.text.char_to_line(start);
+            let mut last_line = self.text.char_to_line(end);
+            if last_line > first_line && self.text.line_to_char(last_line) == end{
+                last_line -= 1;
+            }
+            lines.extend(first_line..=last_line);
+        }
+
+        lines
+    }
+
+    // the selected lines, grouped into runs of consecutive line numbers. each run is (first line, last line)
+    fn selected_line_runs(&self) -> Vec<(usize, usize)>{
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for line_number in self.selected_lines(){
+            match runs.last_mut(){
+                Some((_, last)) if *last + 1 == line_number => *last = line_number,
+                _ => runs.push((line_number, line_number)),
+            }
+        }
+
+        runs
+    }
+
+    // the char range of lines first to last, including the last line's newline, if it has one
+    fn line_run_range(&self, first: usize, last: usize) -> (usize, usize){
+        let end = if last + 1 < self.text.len_lines(){self.text.line_to_char(last + 1)}else{self.text.len_chars()};
+        (self.text.line_to_char(first), end)
+    }
+
+    /// Inserts a copy of every selected line below it. selections move to the copy
+    pub fn duplicate_lines(&mut self){
+        for (first, last) in self.selected_line_runs().into_iter().rev(){
+            let (start, end) = self.line_run_range(first, last);
+            let mut copy = self.text.slice(start..end).to_string();
+            if !copy.ends_with('\n'){
+                copy.push('\n');
+            }
+            // inserting above the original puts the original, and every selection on it, below the copy
+            self.text.insert(start, &copy);
+            shift_selections_after_insert(&mut self.selections, start, copy.chars().count());
+            self.modified = true;
+        }
+    }
+
+    /// Removes every selected line. cursors are left on the line that takes its place, at their stored line position
+    pub fn delete_lines(&mut self){
+        for (first, last) in self.selected_line_runs().into_iter().rev(){
+            let (mut start, end) = self.line_run_range(first, last);
+            // the last line has no newline of its own, so the one ending the line above goes with it
+            if end == self.text.len_chars() && start > 0 && self.text.char(end.saturating_sub(1)) != '\n'{
+                start -= 1;
+            }
+            if start == end{continue;}
+            self.text.remove(start..end);
+            shift_selections_after_remove(&mut self.selections, start, end - start);
+            self.modified = true;
+        }
+        for selection in self.selections.iter_mut(){
+            let line_number = self.text.char_to_line(selection.head());
+            *selection = movement::set_rope_cursor_position_from_line_number(selection.clone(), line_number, self.text.slice(..));
+        }
+    }
+
+    /// Swaps every run of selected lines with the line above it. runs already at the top of the document stay put
+    pub fn move_lines_up(&mut self){
+        for (first, last) in self.selected_line_runs(){
+            if first == 0{continue;}
+            self.swap_line_runs(first - 1, first, last);
+        }
+    }
+
+    /// Swaps every run of selected lines with the line below it. the empty line following a final newline does not count
+    pub fn move_lines_down(&mut self){
+        for (first, last) in self.selected_line_runs().into_iter().rev(){
+            let below = last + 1;
+            let below_is_trailing_empty_line = below + 1 == self.text.len_lines() && self.text.line(below).len_chars() == 0;
+            if below >= self.text.len_lines() || below_is_trailing_empty_line{continue;}
+            self.swap_line_runs(first, below, below);
+        }
+    }
+
+    // swaps lines first..second with lines second..=last, in place. selections move with the lines they are on, 
+    // including cursors at the end of either group. the text keeps its length
+    fn swap_line_runs(&mut self, first: usize, second: usize, last: usize){
+        let (start, end) = self.line_run_range(first, last);
+        let lines: Vec<String> = (first..=last).map(|line_number| {
+            self.text.line(line_number).chars().filter(|char| *char != '\n').collect()
+        }).collect();
+        let (upper, lower) = lines.split_at(second - first);
+        let upper = upper.join("\n");
+        let lower = lower.join("\n");
+        let upper_length = upper.chars().count();
+        let lower_length = lower.chars().count();
+        let mut swapped = format!("{}\n{}", lower, upper);
+        if self.text.slice(start..end).chars().last() == Some('\n'){
+            swapped.push('\n');
+        }
+        self.text.remove(start..end);
+        self.text.insert(start, &swapped);
+        self.modified = true;
+
+        // the newline between the groups stays between them, so each group moves by the other's length plus one
+        let lower_start = start + upper_length + 1;
+        let swap_position = |position: usize| {
+            if position >= start && position < lower_start{
+                position + lower_length + 1
+            }else if position >= lower_start && position <= lower_start + lower_length{
+                position - upper_length - 1
+            }else{
+                position
             }
+        };
+        for selection in self.selections.iter_mut(){
+            selection.set_anchor(swap_position(selection.anchor()));
+            selection.set_head(swap_position(selection.head()));
+        }
+    }
+
+    /// Joins every run of selected lines into one line. a single selected line is joined with the line below it. the 
+    /// leading whitespace of each joined line is replaced with a single space
+    pub fn join_lines(&mut self){
+        for (first, last) in self.selected_line_runs().into_iter().rev(){
+            let last = if first == last{last + 1}else{last};
+            // the empty line after a trailing newline is not a line to join, so the trailing newline stays
+            let mut last_line = self.text.len_lines().saturating_sub(1);
+            if last_line > 0 && self.text.line(last_line).len_chars() == 0{
+                last_line -= 1;
+            }
+            for line_number in (first + 1..=last.min(last_line)).rev(){
+                let line_start = self.text.line_to_char(line_number);
+                let newline = line_start - 1;
+                let indentation = get_first_non_whitespace_character_index(self.text.line(line_number));
+                let joined_line_is_empty = indentation == line_width_excluding_newline(self.text.line(line_number));
+                let previous_line_is_empty = self.text.line(line_number - 1).len_chars() == 1;
+                self.text.remove(newline..line_start + indentation);
+                shift_selections_after_remove(&mut self.selections, newline, 1 + indentation);
+                if !joined_line_is_empty && !previous_line_is_empty{
+                    self.text.insert_char(newline, ' ');
+                    shift_selections_after_insert(&mut self.selections, newline + 1, 1);
+                }
+                self.modified = true;
+            }
+        }
+        self.update_stored_line_positions();
+    }
+
+    /// Inserts a new line above the first line of each selection, indented to match it, and moves the cursor onto it
+    pub fn open_line_above(&mut self){
+        self.open_line(true);
+    }
+
+    /// Inserts a new line below the last line of each selection, indented to match it, and moves the cursor onto it
+    pub fn open_line_below(&mut self){
+        self.open_line(false);
+    }
+
+    fn open_line(&mut self, above: bool){
+        // from the bottom up, so each insert leaves the positions of those still to be handled alone
+        let mut order: Vec<usize> = (0..self.selections.len()).collect();
+        order.sort_by_key(|index| std::cmp::Reverse(self.selections[*index].head().max(self.selections[*index].anchor())));
+        for index in order{
+            let selection = &self.selections[index];
+            let line_number = if above{
+                self.text.char_to_line(selection.head().min(selection.anchor()))
+            }else{
+                self.text.char_to_line(selection.head().max(selection.anchor()))
+            };
+            let line = self.text.line(line_number);
+            let indentation: String = line.chars().take(get_first_non_whitespace_character_index(line)).collect();
+            let indentation_length = indentation.chars().count();
+            let line_start = self.text.line_to_char(line_number);
+            let (position, inserted, cursor) = if above{
+                (line_start, format!("{}\n", indentation), line_start + indentation_length)
+            }else{
+                let line_end = line_start + line_width_excluding_newline(line);
+                (line_end, format!("\n{}", indentation), line_end + 1 + indentation_length)
+            };
+            self.text.insert(position, &inserted);
+            shift_selections_after_insert(&mut self.selections, position, inserted.chars().count());
+            self.selections[index] = Selection::new(cursor, cursor, indentation_length);
+            self.modified = true;
+        }
+    }
+
+    fn update_stored_line_positions(&mut self){
+        for selection in self.selections.iter_mut(){
+            let line_start = self.text.line_to_char(self.text.char_to_line(selection.head()));
+            selection.set_stored_line_position(selection.head() - line_start);
         }
     }
 
@@ -204,19 +792,31 @@ impl Document{
     pub fn backspace(&mut self){
         self.modified = true;
 
-        for selection in self.selections.iter_mut(){
+        for index in self.selections_bottom_up(){
+            let selection = &mut self.selections[index];
+            let length = self.text.len_chars();
             let cursor_line_position = selection.head() - self.text.line_to_char(self.text.char_to_line(selection.head()));
+            let indent_width = self.indent.width;
+            let previous = selection.head().checked_sub(1).and_then(|index| self.text.get_char(index));
+            let next = self.text.get_char(selection.head());
             
-            if cursor_line_position >= TAB_WIDTH
+            // an empty pair goes as a whole
+            if self.auto_pairs && AUTO_PAIRS.iter().any(|(open, close)| previous == Some(*open) && next == Some(*close)){
+                *selection = movement::move_cursor_left(selection.clone(), self.text.slice(..));
+                self.text = Document::delete_at_cursor(selection.clone(), self.text.slice(..));
+                self.text = Document::delete_at_cursor(selection.clone(), self.text.slice(..));
+            }
+            else if self.indent.style == IndentStyle::Spaces
+            && cursor_line_position >= indent_width
             // handles case where user adds a space after a tab, and wants to delete only the space
-            && cursor_line_position % TAB_WIDTH == 0
-            // if previous 4 chars are spaces, delete 4. otherwise, use default behavior
+            && cursor_line_position.is_multiple_of(indent_width)
+            // if previous indent width chars are spaces, delete them all. otherwise, use default behavior
             && slice_is_all_spaces(
-                self.text.line(self.text.char_to_line(selection.head())).as_str().unwrap(),
-                cursor_line_position - TAB_WIDTH,
+                self.text.line(self.text.char_to_line(selection.head())),
+                cursor_line_position - indent_width,
                 cursor_line_position
             ){
-                for _ in 0..TAB_WIDTH{
+                for _ in 0..indent_width{
                     *selection = movement::move_cursor_left(selection.clone(), self.text.slice(..));
                     self.text = Document::delete_at_cursor(selection.clone(), self.text.slice(..));
                 }
@@ -225,6 +825,10 @@ impl Document{
                 *selection = movement::move_cursor_left(selection.clone(), self.text.slice(..));
                 self.text = Document::delete_at_cursor(selection.clone(), self.text.slice(..));
             }
+            let (head, removed) = (selection.head(), length - self.text.len_chars());
+            let selection = selection.clone();
+            shift_selections_after_remove(&mut self.selections, head, removed);
+            self.selections[index] = selection;
         }
     }
 
@@ -372,20 +976,142 @@ impl Document{
         }
     }
 
-    pub fn save(&mut self) -> Result<(), Box<dyn Error>>{
+    pub fn save(&mut self) -> Result<(), EditorError>{
+        if self.read_only{
+            return Err(EditorError::ReadOnly{path: self.file_name.as_ref().map(PathBuf::from)});
+        }
         if let Some(file_name) = &self.file_name{ // does nothing if file_name is None
-            self.text.write_to(BufWriter::new(fs::File::create(file_name)?))?;
+            let path = Path::new(file_name);
+            let bytes = encoding::encode(self.text.slice(..), self.encoding, self.has_bom)?;
+            fs::write(path, bytes).map_err(|e| EditorError::io(path, e))?;
             
             self.modified = false;
+            // the saved file now holds everything the swap file did
+            if let (Some(swap_path), false) = (&self.swap_path, self.recovery_available){
+                swap::remove(swap_path)?;
+            }
         }
         
         Ok(())
     }
 
-    pub fn go_to(&mut self, line_number: usize){
+    pub fn recovery_available(&self) -> bool{
+        self.recovery_available
+    }
+
+    pub fn swap_path(&self) -> Option<&Path>{
+        self.swap_path.as_deref()
+    }
+
+    /// Claims the swap file found when opening, for when another session already has this file open. 
+    /// the swap file holds that session's live edits, so there is nothing to recover
+    pub fn share_swap(&mut self){
+        self.recovery_available = false;
+    }
+
+    /// Persists the unsaved text and selections to this document's swap file. does nothing for unnamed or unmodified documents, or while an older swap file is still awaiting recovery
+    pub fn write_swap(&self) -> Result<(), EditorError>{
+        if let Some(swap_path) = &self.swap_path{
+            if self.modified && !self.recovery_available{
+                swap::write(
+                    swap_path, 
+                    &SwapFile{text: self.text.to_string(), selections: self.selections.clone()}
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes this document's swap file, for when the document is closed cleanly
+    pub fn remove_swap(&self) -> Result<(), EditorError>{
+        if let Some(swap_path) = &self.swap_path{
+            if !self.recovery_available{
+                swap::remove(swap_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the document text and selections with those stored in its swap file
+    pub fn recover_swap(&mut self) -> Result<(), EditorError>{
+        if !self.recovery_available{
+            return Err(EditorError::NoSwapFile);
+        }
+        if let Some(swap_path) = &self.swap_path{
+            let swap_file = swap::read(swap_path)?;
+            self.text = Rope::from(swap_file.text);
+            self.selections = swap_file.selections.into_iter()
+                .filter(|selection| selection.head() <= self.text.len_chars() && selection.anchor() <= self.text.len_chars())
+                .collect();
+            if self.selections.is_empty(){
+                self.selections.push(Selection::default());
+            }
+            self.modified = true;
+            self.recovery_available = false;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the swap file awaiting recovery, keeping the document as it was read from disk
+    pub fn discard_swap(&mut self) -> Result<(), EditorError>{
+        if !self.recovery_available{
+            return Err(EditorError::NoSwapFile);
+        }
+        if let Some(swap_path) = &self.swap_path{
+            swap::remove(swap_path)?;
+            self.recovery_available = false;
+        }
+
+        Ok(())
+    }
+
+    /// Where the client is in this document, for recording in a session file. unnamed documents have nothing to reopen
+    pub fn session_state(&self) -> Option<DocumentState>{
+        // the server may be restarted from another working directory
+        let file_path = PathBuf::from(self.file_name.as_ref()?);
+        Some(DocumentState{
+            file_path: fs::canonicalize(&file_path).unwrap_or(file_path),
+            selections: self.selections.clone(),
+            vertical_start: self.client_view.vertical_start,
+            horizontal_start: self.client_view.horizontal_start,
+        })
+    }
+
+    /// Restores selections and scroll offsets recorded by session_state. the file may have changed since, so selections 
+    /// past the end of the text are dropped, and the view is kept within the document
+    pub fn restore_session_state(&mut self, state: &DocumentState){
+        if self.large_file.is_none(){
+            self.selections = state.selections.iter()
+                .filter(|selection| selection.head() <= self.text.len_chars() && selection.anchor() <= self.text.len_chars())
+                .cloned()
+                .collect();
+            if self.selections.is_empty(){
+                self.selections.push(Selection::default());
+            }
+        }
+        self.client_view.vertical_start = state.vertical_start.min(self.len_lines().saturating_sub(1));
+        self.client_view.horizontal_start = state.horizontal_start;
+    }
+
+    /// Moves the primary cursor to a 0 based line number, and optionally a 0 based column. the column is restricted to the line end
+    pub fn go_to(&mut self, line_number: usize, column: Option<usize>){
+        // there is no cursor in a large file. scroll the view to the line instead
+        if self.large_file.is_some(){
+            if line_number < self.len_lines(){
+                self.client_view.vertical_start = line_number;
+            }
+            return;
+        }
+
         Document::clear_non_primary_selections(&mut self.selections);
         match self.selections.get_mut(0){
             Some(selection) => {
+                if let Some(column) = column{
+                    selection.set_stored_line_position(column);
+                }
                 *selection = movement::set_rope_cursor_position_from_line_number(
                     selection.clone(), 
                     line_number, 
@@ -401,7 +1127,7 @@ impl Document{
     }
 
     pub fn scroll_client_view_down(&mut self, amount: usize){
-        if self.client_view.vertical_start + self.client_view.height + amount <= self.text.len_lines(){
+        if self.client_view.vertical_start + self.client_view.height + amount <= self.len_lines(){
             self.client_view.vertical_start = self.client_view.vertical_start.saturating_add(amount);
         }
     }
@@ -409,14 +1135,20 @@ impl Document{
         self.client_view.horizontal_start = self.client_view.horizontal_start.saturating_sub(amount);
     }
     pub fn scroll_client_view_right(&mut self, amount: usize){
-        let mut longest = 0;
-        for line in self.text.lines(){
-            let line_width = line_width_excluding_newline(line);
-
-            if line_width > longest{
-                longest = line_width;
+        // only lines in view are measured, so scrolling stays cheap regardless of document length
+        let vertical_start = self.client_view.vertical_start.min(self.len_lines());
+        let longest = match &self.large_file{
+            Some(large_file) => {
+                large_file.lines(vertical_start, self.client_view.height).unwrap_or_default().iter()
+                    .map(|line| indent::display_column(line.chars(), usize::MAX, self.indent.width))
+                    .max().unwrap_or(0)
             }
-        }
+            None => {
+                self.text.lines_at(vertical_start).take(self.client_view.height)
+                    .map(|line| indent::display_column(line.chars().filter(|char| *char != '\n'), usize::MAX, self.indent.width))
+                    .max().unwrap_or(0)
+            }
+        };
 
         if self.client_view.horizontal_start + self.client_view.width + amount <= longest{
             self.client_view.horizontal_start = self.client_view.horizontal_start.saturating_add(amount);
@@ -427,9 +1159,11 @@ impl Document{
     }
 
     pub fn scroll_view_following_cursor(&mut self) -> bool{
+        if self.large_file.is_some(){return false;}
+
         // following last cursor pushed to cursors vec
         //let cursor = self.cursors.last().expect("No cursor. This should be impossible");
-        let cursor = Document::rope_cursor_position_to_document_cursor_position(self.selections.last().expect("No cursor. This should be impossible.").clone(), self.text.slice(..));
+        let cursor = self.display_cursor_position(self.selections.last().expect("No cursor. This should be impossible.").clone());
         //
 
         let mut should_update_client_view = false;
@@ -462,31 +1196,35 @@ impl Document{
 
     pub fn get_client_view_text(&self) -> String{
         let mut client_view_text = String::new();
-        for (y, line) in self.text.lines().enumerate(){
-            let mut bounded_line = String::new();
-            if y >= self.client_view.vertical_start
-            && y <= (self.client_view.height.saturating_sub(1) + self.client_view.vertical_start){
-                for (x, char) in line.chars().enumerate(){
-                    if x >= self.client_view.horizontal_start
-                    && x <= (self.client_view.width.saturating_sub(1) + self.client_view.horizontal_start)
-                    && char != '\n'{
-                        bounded_line.push(char);
-                    }
+        let vertical_start = self.client_view.vertical_start.min(self.len_lines());
+        match &self.large_file{
+            Some(large_file) => {
+                for line in large_file.lines(vertical_start, self.client_view.height).unwrap_or_default(){
+                    client_view_text.push_str(format!("{}\n", Document::bound_line_to_client_view(line.chars(), &self.client_view, self.indent.width)).as_str());
+                }
+            }
+            None => {
+                for line in self.text.lines_at(vertical_start).take(self.client_view.height){
+                    client_view_text.push_str(format!("{}\n", Document::bound_line_to_client_view(line.chars(), &self.client_view, self.indent.width)).as_str());
                 }
-                client_view_text.push_str(format!("{}\n", bounded_line).as_str());
             }
         }
 
         client_view_text
     }
+    // the view is measured in display columns, so tabs are expanded before it is applied
+    fn bound_line_to_client_view(line: impl Iterator<Item = char>, client_view: &View, tab_width: usize) -> String{
+        indent::expand_tabs(line.filter(|char| *char != '\n'), tab_width).chars()
+            .skip(client_view.horizontal_start)
+            .take(client_view.width)
+            .collect()
+    }
 
     pub fn get_client_view_line_numbers(&self)-> String{
         let mut client_view_line_numbers = String::new();
-        for (y, _) in self.text.lines().enumerate(){
-            if y >= self.client_view.vertical_start
-            && y <= (self.client_view.height.saturating_sub(1) + self.client_view.vertical_start){
-                client_view_line_numbers.push_str(&format!("{}\n", y.saturating_add(1)));
-            }
+        let end = self.client_view.vertical_start.saturating_add(self.client_view.height).min(self.len_lines());
+        for y in self.client_view.vertical_start..end{
+            client_view_line_numbers.push_str(&format!("{}\n", y.saturating_add(1)));
         }
 
         client_view_line_numbers
@@ -494,12 +1232,10 @@ impl Document{
 
     pub fn get_client_cursor_positions(&self) -> Vec<Position>{
         let mut positions = Vec::new();
+        if self.large_file.is_some(){return positions;}
         for cursor in &self.selections{
             if let Some(client_cursor) = Document::client_view_cursor_position(
-                Document::rope_cursor_position_to_document_cursor_position(
-                    cursor.clone(), 
-                    self.text.slice(..)
-                ), 
+                self.display_cursor_position(cursor.clone()), 
                 self.client_view.clone()
             ){
                 positions.push(client_cursor);
@@ -507,6 +1243,30 @@ impl Document{
         }
         positions
     }
+    /// The client view positions of the bracket under the primary cursor and its match, for highlighting. none when the 
+    /// primary cursor is not on a bracket with a match in view. this runs on every response, so the search for the match 
+    /// is kept to the visible lines, however large the document
+    pub fn get_client_matching_brackets(&self) -> Vec<Position>{
+        if self.large_file.is_some(){return Vec::new();}
+        let visible_lines = self.client_view.vertical_start..self.client_view.vertical_start.saturating_add(self.client_view.height);
+        let Some((bracket, partner)) = self.bracket_pair(self.selections.last().unwrap().head(), visible_lines) else{return Vec::new();};
+        [bracket, partner].into_iter().filter_map(|position| {
+            Document::client_view_cursor_position(
+                self.display_cursor_position(Selection::new(position, position, 0)), 
+                self.client_view.clone()
+            )
+        }).collect()
+    }
+    // like rope_cursor_position_to_document_cursor_position, but columns are display columns, with tabs expanded
+    fn display_cursor_position(&self, selection: Selection) -> Selection2d{
+        let cursor = Document::rope_cursor_position_to_document_cursor_position(selection, self.text.slice(..));
+        let display_position = |position: &Position| Position::new(
+            indent::display_column(self.text.line(position.y()).chars(), position.x(), self.indent.width), 
+            position.y()
+        );
+        
+        Selection2d::new(display_position(cursor.head()), display_position(cursor.anchor()))
+    }
     //TODO: return head and anchor so selections can be displayed
     // translates a document cursor position to a client view cursor position. if outside client view, returns None
     fn client_view_cursor_position(doc_cursor: Selection2d, client_view: View) -> Option<Position>{
@@ -526,45 +1286,222 @@ pub fn line_width_excluding_newline(line: RopeSlice) -> usize{
     let mut line_width = 0;
     for char in line.chars(){
         if char != '\n'{
-            line_width = line_width + 1;
+            line_width += 1;
         }
     }
     line_width
 }
 
 //TODO: handle graphemes instead of chars?
+/// The number of leading spaces and tabs. a line of only whitespace is all indentation
 pub fn get_first_non_whitespace_character_index(line: RopeSlice) -> usize{
-    if line.len_chars() == 0{return 0;}
+    line.chars().take_while(|char| *char == ' ' || *char == '\t').count()
+}
 
-    for (index, char) in line.chars().enumerate(){
-        if char == ' '{/*do nothing*/}
-        else{
-            return index;
+// the closer to insert after c, if c opens an auto pair and the cursor is not inside a word. quotes also do not pair 
+// straight after a word, where they are more likely an apostrophe or a closing quote
+fn auto_pair_closer(c: char, previous: Option<char>, next: Option<char>) -> Option<char>{
+    let (open, close) = AUTO_PAIRS.iter().find(|(open, _)| *open == c)?;
+    let is_word_char = |char: Option<char>| char.is_some_and(|char| char.is_alphanumeric() || char == '_');
+    if is_word_char(next) || (open == close && is_word_char(previous)){
+        return None;
+    }
+
+    Some(*close)
+}
+
+// the pair char is one half of. chars that are not part of an auto pair pair with themselves
+fn surround_pair(char: char) -> (char, char){
+    AUTO_PAIRS.iter()
+        .find(|(open, close)| *open == char || *close == char)
+        .copied()
+        .unwrap_or((char, char))
+}
+
+// the positions of the nearest open before start and close from end on, enclosing start..end. brackets are matched 
+// with nesting, so a pair between them and the selection is skipped. identical open and close chars, like quotes, can 
+// not nest, and the nearest on either side are used. a selection starting on an open bracket uses that bracket's pair, 
+// when it encloses the selection
+fn find_enclosing_pair(text: RopeSlice, start: usize, end: usize, open: char, close: char) -> Option<(usize, usize)>{
+    if open != close && text.get_char(start) == Some(open){
+        if let Some(close_position) = find_unmatched_close(text, start + 1, open, close).filter(|position| position + 1 >= end){
+            return Some((start, close_position));
         }
     }
 
-    0
+    let mut open_position = None;
+    let mut depth = 0;
+    let mut chars = text.chars_at(start);
+    let mut position = start;
+    while let Some(char) = chars.prev(){
+        position -= 1;
+        if char == open{
+            if depth == 0{
+                open_position = Some(position);
+                break;
+            }
+            depth -= 1;
+        }else if char == close{
+            depth += 1;
+        }
+    }
+
+    let open_position = open_position?;
+    find_unmatched_close(text, end, open, close).map(|close_position| (open_position, close_position))
 }
 
-fn slice_is_all_spaces(line: &str, start_of_slice: usize, end_of_slice: usize) -> bool{
-    for grapheme in line[start_of_slice..end_of_slice].graphemes(true){
-        if grapheme != " "{
-            return false;
+// the position of the first close from from on that is not matched by an open after from
+fn find_unmatched_close(text: RopeSlice, from: usize, open: char, close: char) -> Option<usize>{
+    let mut depth = 0;
+    for (position, char) in text.chars_at(from).enumerate().map(|(offset, char)| (from + offset, char)){
+        if char == close{
+            if depth == 0{
+                return Some(position);
+            }
+            depth -= 1;
+        }else if char == open{
+            depth += 1;
         }
     }
 
-    true
+    None
 }
 
-//TODO: calculate cursor_line_position instead of using stored_line_position
-fn distance_to_next_multiple_of_tab_width(cursor: Selection) -> usize{
-    if cursor.stored_line_position() % TAB_WIDTH != 0{
-        TAB_WIDTH - (cursor.stored_line_position() % TAB_WIDTH)
-    }else{
-        0
+// the position of the bracket matching the one at position, skipping nested pairs, and brackets in strings or comments.
+// None if it is not within lines
+fn find_matching_bracket(text: RopeSlice, position: usize, language: Option<&Language>, lines: Range<usize>) -> Option<usize>{
+    let bracket = text.get_char(position)?;
+    let (partner, forward) = BRACKETS.iter().find_map(|(open, close)| {
+        if bracket == *open{
+            Some((*close, true))
+        }else if bracket == *close{
+            Some((*open, false))
+        }else{
+            None
+        }
+    })?;
+    if !lines.contains(&text.char_to_line(position)){return None;}
+    let mut line_start = text.line_to_char(text.char_to_line(position));
+    let mut mask = code_mask(text.line(text.char_to_line(position)), language);
+    if !mask[position - line_start]{return None;}
+
+    let mut depth = 0;
+    let mut current = position;
+    loop{
+        if forward{
+            current += 1;
+            if current >= text.len_chars(){return None;}
+        }else{
+            current = current.checked_sub(1)?;
+        }
+        // the mask is built a line at a time, as the search reaches each line
+        if current < line_start || current >= line_start + mask.len(){
+            let line_number = text.char_to_line(current);
+            if !lines.contains(&line_number){return None;}
+            line_start = text.line_to_char(line_number);
+            mask = code_mask(text.line(line_number), language);
+        }
+        if !mask[current - line_start]{continue;}
+        let char = text.char(current);
+        if char == bracket{
+            depth += 1;
+        }else if char == partner{
+            if depth == 0{
+                return Some(current);
+            }
+            depth -= 1;
+        }
     }
 }
 
+// which chars of line are code, rather than inside a string or comment. strings and comments are recognised a line at 
+// a time, so those spanning lines are not. with no language, every char counts as code
+fn code_mask(line: RopeSlice, language: Option<&Language>) -> Vec<bool>{
+    let chars: Vec<char> = line.chars().collect();
+    let mut mask = vec![true; chars.len()];
+    let Some(language) = language else{return mask;};
+    let starts_with = |index: usize, token: &str| {
+        token.chars().enumerate().all(|(offset, char)| chars.get(index + offset) == Some(&char))
+    };
+
+    let mut quote = None;
+    let mut block_comment_close: Option<&str> = None;
+    let mut index = 0;
+    while index < chars.len(){
+        if let Some(close) = block_comment_close{
+            if starts_with(index, close){
+                let close_length = close.chars().count();
+                mask[index..index + close_length].fill(false);
+                index += close_length;
+                block_comment_close = None;
+                continue;
+            }
+            mask[index] = false;
+        }else if let Some(quote_char) = quote{
+            mask[index] = false;
+            if chars[index] == '\\'{
+                // the escaped char can not end the string
+                if let Some(escaped) = mask.get_mut(index + 1){*escaped = false;}
+                index += 2;
+                continue;
+            }
+            if chars[index] == quote_char{
+                quote = None;
+            }
+        // checked before line comments, since lua's block comment starts with its line comment token
+        }else if let Some((open, close)) = language.block_comment.filter(|(open, _)| starts_with(index, open)){
+            let open_length = open.chars().count();
+            mask[index..index + open_length].fill(false);
+            index += open_length;
+            block_comment_close = Some(close);
+            continue;
+        }else if language.line_comment.is_some_and(|token| starts_with(index, token)){
+            mask[index..].fill(false);
+            break;
+        }else if chars[index] == '"' || chars[index] == '`'{
+            // single quotes are left out, since they are as often lifetimes or apostrophes as strings
+            mask[index] = false;
+            quote = Some(chars[index]);
+        }
+        index += 1;
+    }
+
+    mask
+}
+
+// whether the last non whitespace character of line is an opening bracket or colon
+fn opens_block(line: RopeSlice) -> bool{
+    let mut chars = line.chars_at(line.len_chars());
+    while let Some(char) = chars.prev(){
+        if char == ' ' || char == '\t'{continue;}
+        return matches!(char, '{' | '(' | '[' | ':');
+    }
+
+    false
+}
+
+// keeps selections on the same text after length chars are inserted at position
+fn shift_selections_after_insert(selections: &mut [Selection], position: usize, length: usize){
+    for selection in selections.iter_mut(){
+        if selection.anchor() >= position{selection.set_anchor(selection.anchor() + length);}
+        if selection.head() >= position{selection.set_head(selection.head() + length);}
+    }
+}
+
+// keeps selections on the same text after length chars are removed from position. positions inside the removed text 
+// move to its start
+fn shift_selections_after_remove(selections: &mut [Selection], position: usize, length: usize){
+    let shift = |index: usize| if index >= position + length{index - length}else{index.min(position)};
+    for selection in selections.iter_mut(){
+        selection.set_anchor(shift(selection.anchor()));
+        selection.set_head(shift(selection.head()));
+    }
+}
+
+// start_of_slice and end_of_slice are char indices into line
+fn slice_is_all_spaces(line: RopeSlice, start_of_slice: usize, end_of_slice: usize) -> bool{
+    line.slice(start_of_slice..end_of_slice).chars().all(|char| char == ' ')
+}
 
 
 
@@ -646,7 +1583,7 @@ fn distance_to_next_multiple_of_tab_width(cursor: Selection) -> usize{
     fn clear_cursors_except_main_works(){
         let mut cursors = vec![Selection::default(), Selection::default(), Selection::default()];
         Document::clear_non_primary_selections(&mut cursors);
-        assert!(cursors.get(0).is_some());
+        assert!(!cursors.is_empty());
         assert!(cursors.get(1).is_none());
     }
     
@@ -697,10 +1634,45 @@ fn distance_to_next_multiple_of_tab_width(cursor: Selection) -> usize{
         assert!(new_text == expected_text);
     }
 // AUTO-INDENT
-    //#[test]
-    //fn auto_indent_works(){
-    //    assert!(false);
-    //}
+    #[test]
+    fn enter_copies_indentation_of_current_line(){
+        let text = Rope::from("    idk\n");
+        let (selection, new_text) = Document::enter_at_cursor(Selection::new(7, 7, 7), text.slice(..), "    ");  //    idk[]\n
+        assert!(new_text == "    idk\n    \n");
+        assert!(selection == Selection::new(12, 12, 4));    //    idk\n    []\n
+    }
+    #[test]
+    fn enter_copies_indentation_of_whitespace_only_line(){
+        let text = Rope::from("\t  ");
+        let (selection, new_text) = Document::enter_at_cursor(Selection::new(3, 3, 3), text.slice(..), "    ");  //\t  []
+        assert!(new_text == "\t  \n\t  ");
+        assert!(selection == Selection::new(7, 7, 3));
+    }
+    #[test]
+    fn enter_only_copies_indentation_before_cursor(){
+        let text = Rope::from("    idk");
+        let (selection, new_text) = Document::enter_at_cursor(Selection::new(2, 2, 2), text.slice(..), "    ");  //  []  idk
+        assert!(new_text == "  \n    idk");
+        assert!(selection == Selection::new(5, 5, 2));
+    }
+    #[test]
+    fn enter_indents_one_level_further_after_block_opener(){
+        let text = Rope::from("  fn idk(){ \n");
+        let (selection, new_text) = Document::enter_at_cursor(Selection::new(12, 12, 12), text.slice(..), "    ");
+        assert!(new_text == "  fn idk(){ \n      \n");
+        assert!(selection == Selection::new(19, 19, 6));
+    }
+    #[test]
+    fn enter_moves_later_cursors_down(){
+        let mut doc = Document{
+            text: Rope::from("  a\n  b\n"), 
+            selections: vec![Selection::new(3, 3, 3), Selection::new(7, 7, 3)], 
+            ..Default::default()
+        };
+        doc.enter();
+        assert!(doc.text == "  a\n  \n  b\n  \n");
+        assert!(doc.selections == vec![Selection::new(6, 6, 2), Selection::new(13, 13, 2)]);
+    }
     
 //INSERT CHAR
     #[test]
@@ -717,6 +1689,71 @@ fn distance_to_next_multiple_of_tab_width(cursor: Selection) -> usize{
         assert!(new_text == expected_text);
     }
 
+//AUTO PAIRS
+    #[test]
+    fn insert_char_closes_opening_bracket(){
+        let mut doc = Document{text: Rope::from("idk\n"), selections: vec![Selection::new(3, 3, 3)], ..Default::default()};
+        doc.insert_char('(');
+        assert!(doc.text == "idk()\n");
+        assert!(doc.selections == vec![Selection::new(4, 4, 4)]);
+        doc.insert_char(')');
+        assert!(doc.text == "idk()\n");
+        assert!(doc.selections == vec![Selection::new(5, 5, 5)]);
+    }
+    #[test]
+    fn insert_char_does_not_pair_inside_words(){
+        let mut doc = Document{text: Rope::from("idk\n"), selections: vec![Selection::new(0, 0, 0)], ..Default::default()};
+        doc.insert_char('(');
+        assert!(doc.text == "(idk\n");
+        let mut doc = Document{text: Rope::from("don\n"), selections: vec![Selection::new(3, 3, 3)], ..Default::default()};
+        doc.insert_char('\'');
+        assert!(doc.text == "don'\n");
+    }
+    #[test]
+    fn insert_char_pairs_at_every_cursor(){
+        let mut doc = Document{
+            text: Rope::from("a\nb\n"), 
+            selections: vec![Selection::new(1, 1, 1), Selection::new(3, 3, 1)], 
+            ..Default::default()
+        };
+        doc.insert_char('(');
+        assert!(doc.text == "a()\nb()\n");
+        assert!(doc.selections == vec![Selection::new(2, 2, 2), Selection::new(6, 6, 2)]);
+        doc.backspace();
+        assert!(doc.text == "a\nb\n");
+        assert!(doc.selections == vec![Selection::new(1, 1, 1), Selection::new(3, 3, 1)]);
+    }
+    #[test]
+    fn insert_char_without_auto_pairs_inserts_only_char(){
+        let mut doc = Document{text: Rope::from(")\n"), auto_pairs: false, ..Default::default()};
+        doc.insert_char('[');
+        doc.insert_char(')');
+        assert!(doc.text == "[))\n");
+    }
+    #[test]
+    fn backspace_removes_empty_pair(){
+        let mut doc = Document{text: Rope::from("idk\"\"\n"), selections: vec![Selection::new(4, 4, 4)], ..Default::default()};
+        doc.backspace();
+        assert!(doc.text == "idk\n");
+        assert!(doc.selections == vec![Selection::new(3, 3, 3)]);
+    }
+    #[test]
+    fn backspace_removes_indent_after_non_ascii_text(){
+        let mut doc = Document{text: Rope::from("éééé    x\n"), selections: vec![Selection::new(8, 8, 8)], ..Default::default()};
+        doc.backspace();
+        assert!(doc.text == "ééééx\n");
+        assert!(doc.selections == vec![Selection::new(4, 4, 4)]);
+    }
+    #[test]
+    fn backspace_removes_indent_in_line_longer_than_rope_chunk(){
+        let line = format!("{}    x\n", "idk ".repeat(1000));
+        let mut doc = Document{text: Rope::from(line.as_str()), selections: vec![Selection::new(4004, 4004, 4004)], ..Default::default()};
+        assert!(doc.text.line(0).as_str().is_none());
+        doc.backspace();
+        assert!(doc.text == format!("{}x\n", "idk ".repeat(1000)));
+        assert!(doc.selections == vec![Selection::new(4000, 4000, 4000)]);
+    }
+
 //INSERT SELECTION
     //#[test]
     //fn single_cursor_insert_single_line_selection_works(){
@@ -744,6 +1781,301 @@ fn distance_to_next_multiple_of_tab_width(cursor: Selection) -> usize{
     //    assert!(doc.cursors.last().unwrap().anchor.y() == 0);
     //}
 
+    #[test]
+    fn tab_pads_spaces_to_next_indent_stop(){
+        let mut doc = Document{text: Rope::from("a"), selections: vec![Selection::new(1, 1, 1)], ..Default::default()};
+        doc.set_indent(IndentStyle::Spaces, 2);
+        doc.tab();
+        assert!(doc.text == "a ");
+        doc.tab();
+        assert!(doc.text == "a   ");
+    }
+    #[test]
+    fn tab_inserts_tab_character_with_tab_style(){
+        let mut doc = Document{text: Rope::from("a"), selections: vec![Selection::new(1, 1, 1)], ..Default::default()};
+        doc.set_indent(IndentStyle::Tabs, 8);
+        doc.tab();
+        assert!(doc.text == "a\t");
+    }
+    #[test]
+    fn tab_moves_later_cursors_past_inserted_indent(){
+        let mut doc = Document{text: Rope::from("a\nb"), selections: vec![Selection::new(0, 0, 0), Selection::new(2, 2, 0)], ..Default::default()};
+        doc.set_indent(IndentStyle::Spaces, 2);
+        doc.tab();
+        assert!(doc.text == "  a\n  b");
+        assert!(doc.selections == vec![Selection::new(2, 2, 2), Selection::new(6, 6, 2)]);
+    }
+
+//INDENT LINES
+    #[test]
+    fn tab_with_multi_line_selection_indents_selected_lines(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse\n"), selections: vec![Selection::new(1, 6, 2)], ..Default::default()};
+        doc.tab();
+        assert!(doc.text == "    idk\n    something\nelse\n");
+        assert!(doc.selections == vec![Selection::new(5, 14, 6)]);  //    i[dk\n    so]mething\nelse\n
+    }
+    #[test]
+    fn indent_lines_skips_blank_lines_and_line_where_selection_ends_at_its_start(){
+        let mut doc = Document{text: Rope::from("idk\n\nelse\n"), selections: vec![Selection::new(0, 5, 0)], ..Default::default()};
+        doc.indent_lines();
+        assert!(doc.text == "    idk\n\nelse\n");
+        assert!(doc.selections == vec![Selection::new(4, 9, 0)]);
+    }
+    #[test]
+    fn indent_lines_indents_lines_shared_by_selections_once(){
+        let mut doc = Document{
+            text: Rope::from("idk\nsomething\n"), 
+            selections: vec![Selection::new(0, 5, 1), Selection::new(6, 6, 2)], 
+            ..Default::default()
+        };
+        doc.indent_lines();
+        assert!(doc.text == "    idk\n    something\n");
+        assert!(doc.selections == vec![Selection::new(4, 13, 5), Selection::new(14, 14, 6)]);
+    }
+    #[test]
+    fn dedent_lines_removes_one_indent_unit(){
+        let mut doc = Document{text: Rope::from("      idk\n\tsomething\nelse\n"), selections: vec![Selection::new(8, 13, 2)], ..Default::default()};
+        doc.dedent_lines();
+        assert!(doc.text == "    idk\nsomething\nelse\n");
+        assert!(doc.selections == vec![Selection::new(6, 10, 2)]);
+    }
+    #[test]
+    fn dedent_lines_moves_selection_inside_removed_indentation_to_line_start(){
+        let mut doc = Document{text: Rope::from("    idk\n"), selections: vec![Selection::new(2, 2, 2)], ..Default::default()};
+        doc.dedent_lines();
+        assert!(doc.text == "idk\n");
+        assert!(doc.selections == vec![Selection::new(0, 0, 0)]);
+    }
+
+//TOGGLE COMMENT
+    #[test]
+    fn toggle_comment_aligns_to_minimum_indentation(){
+        let mut doc = Document{
+            text: Rope::from("fn idk(){\n    something();\n\n}\n"), 
+            language: language::from_path(Path::new("idk.rs")), 
+            selections: vec![Selection::new(0, 29, 0)], 
+            ..Default::default()
+        };
+        doc.toggle_comment();
+        assert!(doc.text == "// fn idk(){\n//     something();\n\n// }\n");
+        doc.toggle_comment();
+        assert!(doc.text == "fn idk(){\n    something();\n\n}\n");
+        assert!(doc.selections == vec![Selection::new(0, 29, 1)]);
+    }
+    #[test]
+    fn toggle_comment_comments_when_only_some_lines_are_commented(){
+        let mut doc = Document{
+            text: Rope::from("  # idk\n  something\n"), 
+            language: language::from_path(Path::new("idk.py")), 
+            selections: vec![Selection::new(0, 12, 0)], 
+            ..Default::default()
+        };
+        doc.toggle_comment();
+        assert!(doc.text == "  # # idk\n  # something\n");
+    }
+    #[test]
+    fn toggle_comment_falls_back_to_block_comments(){
+        let mut doc = Document{
+            text: Rope::from("  idk\n"), 
+            language: language::from_path(Path::new("idk.css")), 
+            selections: vec![Selection::new(5, 5, 5)], 
+            ..Default::default()
+        };
+        doc.toggle_comment();
+        assert!(doc.text == "  /* idk */\n");
+        assert!(doc.selections == vec![Selection::new(8, 8, 8)]);
+        doc.toggle_comment();
+        assert!(doc.text == "  idk\n");
+        assert!(doc.selections == vec![Selection::new(5, 5, 5)]);
+    }
+    #[test]
+    fn toggle_comment_without_language_does_nothing(){
+        let mut doc = Document{text: Rope::from("idk\n"), ..Default::default()};
+        doc.toggle_comment();
+        assert!(doc.text == "idk\n");
+        assert!(!doc.is_modified());
+    }
+
+//SURROUND
+    #[test]
+    fn surround_keeps_selection_on_inner_text(){
+        let mut doc = Document{
+            text: Rope::from("idk something\n"), 
+            selections: vec![Selection::new(0, 3, 3), Selection::new(4, 13, 13)], 
+            ..Default::default()
+        };
+        doc.surround('(', ')');
+        assert!(doc.text == "(idk) (something)\n");
+        assert!(doc.selections == vec![Selection::new(1, 4, 4), Selection::new(7, 16, 16)]);
+    }
+    #[test]
+    fn delete_surround_skips_nested_pairs(){
+        let mut doc = Document{text: Rope::from("f(a(b), c)\n"), selections: vec![Selection::new(8, 8, 8)], ..Default::default()};
+        doc.delete_surround(')');
+        assert!(doc.text == "fa(b), c\n");
+        assert!(doc.selections == vec![Selection::new(7, 7, 7)]);
+    }
+    #[test]
+    fn delete_surround_on_open_bracket_uses_its_pair(){
+        let mut doc = Document{text: Rope::from("(a [b] c)\n"), selections: vec![Selection::new(3, 3, 3)], ..Default::default()};
+        doc.delete_surround('[');
+        assert!(doc.text == "(a b c)\n");
+        let mut doc = Document{text: Rope::from("(a (b) c)\n"), selections: vec![Selection::new(3, 3, 3)], ..Default::default()};
+        doc.change_surround('(', '{');
+        assert!(doc.text == "(a {b} c)\n");
+    }
+    #[test]
+    fn delete_surround_without_enclosing_pair_does_nothing(){
+        let mut doc = Document{text: Rope::from("(idk) something\n"), selections: vec![Selection::new(8, 8, 8)], ..Default::default()};
+        doc.delete_surround('(');
+        assert!(doc.text == "(idk) something\n");
+        assert!(!doc.is_modified());
+    }
+    #[test]
+    fn change_surround_replaces_pair(){
+        let mut doc = Document{text: Rope::from("x = \"idk\";\n"), selections: vec![Selection::new(6, 6, 6)], ..Default::default()};
+        doc.change_surround('"', '[');
+        assert!(doc.text == "x = [idk];\n");
+        assert!(doc.selections == vec![Selection::new(6, 6, 6)]);
+    }
+
+//MATCH BRACKET
+    #[test]
+    fn match_bracket_skips_nested_pairs(){
+        let mut doc = Document{text: Rope::from("f(a(b), c)\n"), selections: vec![Selection::new(1, 1, 1)], ..Default::default()};
+        doc.match_bracket(false);
+        assert!(doc.selections == vec![Selection::new(9, 9, 9)]);
+        doc.match_bracket(true);
+        assert!(doc.selections == vec![Selection::new(9, 1, 1)]);
+    }
+    #[test]
+    fn match_bracket_ignores_brackets_in_strings_and_comments(){
+        let mut doc = Document{
+            text: Rope::from("f(\")\", // )\n  x)\n"), 
+            language: language::from_path(Path::new("idk.rs")), 
+            selections: vec![Selection::new(1, 1, 1)], 
+            ..Default::default()
+        };
+        doc.match_bracket(false);
+        assert!(doc.selections == vec![Selection::new(15, 15, 3)]);
+    }
+    #[test]
+    fn match_bracket_off_bracket_does_nothing(){
+        let mut doc = Document{text: Rope::from("idk(x)\n"), selections: vec![Selection::new(1, 1, 1)], ..Default::default()};
+        doc.match_bracket(false);
+        assert!(doc.selections == vec![Selection::new(1, 1, 1)]);
+    }
+    #[test]
+    fn client_matching_brackets_reports_pair_under_primary_cursor(){
+        let mut doc = Document{text: Rope::from("idk(x)\n"), selections: vec![Selection::new(6, 6, 6)], ..Default::default()};
+        doc.set_client_view_size(10, 10);
+        let positions = doc.get_client_matching_brackets();
+        assert!(positions.len() == 2);
+        assert!(positions[0].x() == 5 && positions[1].x() == 3);
+    }
+    #[test]
+    fn client_matching_brackets_only_searches_visible_lines(){
+        let mut doc = Document{text: Rope::from("idk(\nx\n)\n"), selections: vec![Selection::new(3, 3, 3)], ..Default::default()};
+        doc.set_client_view_size(10, 2);
+        assert!(doc.get_client_matching_brackets().is_empty());
+        doc.match_bracket(false);
+        assert!(doc.selections == vec![Selection::new(7, 7, 0)]);
+    }
+
+//LINE COMMANDS
+    #[test]
+    fn duplicate_lines_moves_selection_to_copy(){
+        let mut doc = Document{text: Rope::from("idk\nsomething"), selections: vec![Selection::new(6, 6, 2)], ..Default::default()};
+        doc.duplicate_lines();
+        assert!(doc.text == "idk\nsomething\nsomething");
+        assert!(doc.selections == vec![Selection::new(16, 16, 2)]);
+    }
+    #[test]
+    fn delete_lines_keeps_stored_line_position(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse\n"), selections: vec![Selection::new(0, 2, 2)], ..Default::default()};
+        doc.delete_lines();
+        assert!(doc.text == "something\nelse\n");
+        assert!(doc.selections == vec![Selection::new(2, 2, 2)]);
+    }
+    #[test]
+    fn delete_lines_removes_last_line_with_preceding_newline(){
+        let mut doc = Document{text: Rope::from("idk\nsomething"), selections: vec![Selection::new(6, 6, 2)], ..Default::default()};
+        doc.delete_lines();
+        assert!(doc.text == "idk");
+        assert!(doc.selections == vec![Selection::new(2, 2, 2)]);
+    }
+    #[test]
+    fn move_lines_up_swaps_with_line_above(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse"), selections: vec![Selection::new(6, 16, 2)], ..Default::default()};
+        doc.move_lines_up();
+        assert!(doc.text == "something\nelse\nidk");
+        assert!(doc.selections == vec![Selection::new(2, 12, 2)]);
+    }
+    #[test]
+    fn move_lines_up_at_document_start_does_nothing(){
+        let mut doc = Document{text: Rope::from("idk\nsomething"), selections: vec![Selection::new(1, 1, 1)], ..Default::default()};
+        doc.move_lines_up();
+        assert!(doc.text == "idk\nsomething");
+        assert!(!doc.is_modified());
+    }
+    #[test]
+    fn move_lines_down_swaps_with_line_below(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\n"), selections: vec![Selection::new(1, 1, 1)], ..Default::default()};
+        doc.move_lines_down();
+        assert!(doc.text == "something\nidk\n");
+        assert!(doc.selections == vec![Selection::new(11, 11, 1)]);
+        doc.move_lines_down();
+        assert!(doc.text == "something\nidk\n");
+    }
+    #[test]
+    fn move_lines_down_without_trailing_newline(){
+        let mut doc = Document{text: Rope::from("a\nb"), selections: vec![Selection::new(0, 0, 0)], ..Default::default()};
+        doc.move_lines_down();
+        assert!(doc.text == "b\na");
+        assert!(doc.selections == vec![Selection::new(2, 2, 0)]);
+    }
+    #[test]
+    fn move_lines_up_moves_cursor_at_document_end(){
+        let mut doc = Document{text: Rope::from("a\nbc"), selections: vec![Selection::new(4, 4, 2)], ..Default::default()};
+        doc.move_lines_up();
+        assert!(doc.text == "bc\na");
+        assert!(doc.selections == vec![Selection::new(2, 2, 2)]);
+    }
+    #[test]
+    fn join_lines_replaces_indentation_with_single_space(){
+        let mut doc = Document{text: Rope::from("idk(\n    something\n)\n"), selections: vec![Selection::new(0, 10, 1)], ..Default::default()};
+        doc.join_lines();
+        assert!(doc.text == "idk( something\n)\n");
+        assert!(doc.selections == vec![Selection::new(0, 6, 6)]);
+        doc.join_lines();
+        assert!(doc.text == "idk( something )\n");
+    }
+    #[test]
+    fn join_lines_keeps_trailing_newline(){
+        let mut doc = Document{text: Rope::from("a\nb\n"), selections: vec![Selection::new(2, 2, 0)], ..Default::default()};
+        doc.join_lines();
+        assert!(doc.text == "a\nb\n");
+        assert!(!doc.is_modified());
+    }
+    #[test]
+    fn open_line_below_copies_indentation(){
+        let mut doc = Document{text: Rope::from("  idk\nsomething"), selections: vec![Selection::new(3, 3, 3)], ..Default::default()};
+        doc.open_line_below();
+        assert!(doc.text == "  idk\n  \nsomething");
+        assert!(doc.selections == vec![Selection::new(8, 8, 2)]);
+    }
+    #[test]
+    fn open_line_above_moves_other_selections_down(){
+        let mut doc = Document{
+            text: Rope::from("idk\nsomething"), 
+            selections: vec![Selection::new(1, 1, 1), Selection::new(5, 5, 1)], 
+            ..Default::default()
+        };
+        doc.open_line_above();
+        assert!(doc.text == "\nidk\n\nsomething");
+        assert!(doc.selections == vec![Selection::new(0, 0, 0), Selection::new(5, 5, 0)]);
+    }
+
 //DELETE
     #[test]
     fn delete_works(){
@@ -834,7 +2166,102 @@ fn distance_to_next_multiple_of_tab_width(cursor: Selection) -> usize{
     //    assert!(cursor.anchor.y() == 0);
     //}
 
-//goto
+//SET ENCODING
+    #[test]
+    fn set_encoding_marks_document_modified(){
+        let mut doc = Document{text: Rope::from("idk\n"), ..Default::default()};
+        assert!(doc.set_encoding(Encoding::Utf16Le, true).is_ok());
+        assert!(doc.encoding() == Encoding::Utf16Le);
+        assert!(doc.has_bom());
+        assert!(doc.is_modified());
+    }
+    #[test]
+    fn set_encoding_fails_without_changes_when_text_can_not_be_encoded(){
+        let mut doc = Document{text: Rope::from("€\n"), ..Default::default()};
+        assert!(doc.set_encoding(Encoding::Latin1, false).is_err());
+        assert!(doc.encoding() == Encoding::Utf8);
+        assert!(!doc.is_modified());
+    }
+
+//READ ONLY
+    #[test]
+    fn open_read_only_refuses_save(){
+        let path = std::env::temp_dir().join(format!("nlo_read_only_{}.txt", std::process::id()));
+        let state_dir = std::env::temp_dir().join(format!("nlo_read_only_state_{}", std::process::id()));
+        fs::write(&path, "idk\n").unwrap();
+        let mut doc = Document::open(&path, true, false, &state_dir).unwrap();
+        assert!(doc.is_read_only());
+        assert!(doc.save().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn open_writable_file_is_not_read_only(){
+        let path = std::env::temp_dir().join(format!("nlo_writable_{}.txt", std::process::id()));
+        let state_dir = std::env::temp_dir().join(format!("nlo_writable_state_{}", std::process::id()));
+        fs::write(&path, "idk\n").unwrap();
+        let doc = Document::open(&path, false, false, &state_dir).unwrap();
+        assert!(!doc.is_read_only());
+        fs::remove_file(&path).unwrap();
+    }
+
+//OPEN BINARY
+    #[test]
+    fn open_refuses_binary_file_unless_forced(){
+        let path = std::env::temp_dir().join(format!("nlo_binary_{}.bin", std::process::id()));
+        let state_dir = std::env::temp_dir().join(format!("nlo_binary_state_{}", std::process::id()));
+        fs::write(&path, [0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0]).unwrap();
+        match Document::open(&path, false, false, &state_dir){
+            Err(e) => assert!(e == EditorError::FileIsBinary{path: path.clone()}),
+            Ok(_) => panic!("binary file should not open without force"),
+        }
+        assert!(Document::open(&path, false, true, &state_dir).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+//SAVE
+    #[test]
+    fn save_writes_text_and_clears_modified(){
+        let path = std::env::temp_dir().join(format!("nlo_save_{}.txt", std::process::id()));
+        let state_dir = std::env::temp_dir().join(format!("nlo_save_state_{}", std::process::id()));
+        fs::write(&path, "idk\n").unwrap();
+        let mut doc = Document::open(&path, false, false, &state_dir).unwrap();
+        doc.insert_char('x');
+        assert!(doc.save().is_ok());
+        assert!(!doc.is_modified());
+        assert!(fs::read_to_string(&path).unwrap() == "xidk\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+//SESSION STATE
+    #[test]
+    fn restore_session_state_drops_selections_past_end_of_text(){
+        let mut doc = Document{text: Rope::from("idk\n"), ..Default::default()};
+        let state = DocumentState{
+            file_path: PathBuf::from("idk.txt"), 
+            selections: vec![Selection::new(2, 2, 2), Selection::new(20, 20, 0)], 
+            vertical_start: 9, 
+            horizontal_start: 1
+        };
+        doc.restore_session_state(&state);
+        assert!(doc.selections == vec![Selection::new(2, 2, 2)]);
+        assert!(doc.client_view.vertical_start == 1);
+        assert!(doc.client_view.horizontal_start == 1);
+    }
+
+//GOTO
+    #[test]
+    fn go_to_with_column_works(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse"), ..Default::default()};
+        doc.go_to(1, Some(4));
+        assert!(doc.selections == vec![Selection::new(8, 8, 4)]);  //idk\nsome[]thing\nelse
+    }
+    #[test]
+    fn go_to_with_column_past_line_end_restricts_cursor_to_line_end(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse"), ..Default::default()};
+        doc.go_to(0, Some(7));
+        assert!(doc.selections == vec![Selection::new(3, 3, 7)]);  //idk[]\nsomething\nelse
+    }
+
 
 //scroll client view down
 //scroll client view left
@@ -846,12 +2273,53 @@ fn distance_to_next_multiple_of_tab_width(cursor: Selection) -> usize{
 //get client view text
     #[test]
     fn get_client_view_text_works(){
-        let mut doc = Document::default();
-        doc.text = Rope::from("idk\nsomething\nelse\n");
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse\n"), ..Default::default()};
         doc.set_client_view_size(2, 2);
         println!("{:?}", doc.get_client_view_text());
-        assert!(doc.get_client_view_text() == String::from("id\nso\n"));
+        assert!(doc.get_client_view_text() == "id\nso\n");
+    }
+    #[test]
+    fn get_client_view_text_works_when_scrolled(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse\n"), ..Default::default()};
+        doc.set_client_view_size(3, 2);
+        doc.client_view.vertical_start = 1;
+        doc.client_view.horizontal_start = 1;
+        assert!(doc.get_client_view_text() == "ome\nlse\n");
+    }
+    #[test]
+    fn get_client_view_text_works_in_large_file_mode(){
+        let path = std::env::temp_dir().join(format!("nlo_large_file_view_{}.txt", std::process::id()));
+        fs::write(&path, "idk\nsomething\nelse\n").unwrap();
+        let mut doc = Document::open_large_file(&path, false).unwrap();
+        assert!(doc.is_read_only());
+        doc.set_client_view_size(3, 2);
+        doc.go_to(1, None);
+        assert!(doc.get_client_view_text() == "som\nels\n");
+        assert!(doc.get_client_view_line_numbers() == "2\n3\n");
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn get_client_view_text_expands_tabs(){
+        let mut doc = Document{text: Rope::from("\tidk\n"), ..Default::default()};
+        doc.set_indent(IndentStyle::Tabs, 4);
+        doc.set_client_view_size(6, 1);
+        doc.client_view.horizontal_start = 2;
+        assert!(doc.get_client_view_text() == "  idk\n");
+    }
+    #[test]
+    fn client_cursor_positions_are_display_columns(){
+        let mut doc = Document{text: Rope::from("\tidk\n"), selections: vec![Selection::new(2, 2, 2)], ..Default::default()};
+        doc.set_indent(IndentStyle::Tabs, 4);
+        doc.set_client_view_size(10, 1);
+        assert!(doc.get_client_cursor_positions() == vec![Position::new(5, 0)]);
     }
 //get client view line numbers
+    #[test]
+    fn get_client_view_line_numbers_works(){
+        let mut doc = Document{text: Rope::from("idk\nsomething\nelse"), ..Default::default()};
+        doc.set_client_view_size(2, 5);
+        doc.client_view.vertical_start = 1;
+        assert!(doc.get_client_view_line_numbers() == "2\n3\n");
+    }
 //get client cursor positions
 //}
@@ -1,36 +1,372 @@
 use crate::document::Document;
-use std::{collections::HashMap, error::Error, path::PathBuf};
+use crate::error::EditorError;
+use crate::session::{self, SessionFile};
+use crate::swap;
+use std::{collections::{hash_map::DefaultHasher, HashMap}, hash::{Hash, Hasher}, path::{Path, PathBuf}, sync::{Arc, Mutex, MutexGuard, PoisonError}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+/// How long the document of a client that disconnected without closing it is kept, in case the client comes back
+pub const ORPHAN_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
 
 
-#[derive(Default)]
+
+/// Documents belong to a session, rather than a connection, so a client that reconnects can resume where it left off.
+/// each document has its own lock, so a slow operation on one document does not block clients working in another.
+/// the registry itself is only locked long enough to look up, insert, or remove a document
 pub struct Editor{
-    documents: HashMap<String, Document>,
+    documents: Mutex<HashMap<String, Arc<Mutex<Document>>>>,
+    /// documents whose session has no connection, because the client disconnected without closing them, and when that happened
+    orphaned: Mutex<HashMap<String, Instant>>,
+    /// where swap files and session files are kept. swap::state_dir(), outside of tests
+    state_dir: PathBuf,
 }
 impl Editor{
-    pub fn document(&self, client_address: &str) -> Option<&Document>{
-        if let Some(doc) = self.documents.get(client_address){
-            return Some(doc);
+    pub fn new(state_dir: PathBuf) -> Self{
+        Self{documents: Mutex::default(), orphaned: Mutex::default(), state_dir}
+    }
+    pub fn document(&self, session_token: &str) -> Option<Arc<Mutex<Document>>>{
+        lock(&self.documents).get(session_token).cloned()
+    }
+    pub fn open_document(&self, path: &PathBuf, read_only: bool, force: bool, session_token: &str) -> Result<(), EditorError>{
+        // read the file before taking the registry lock, so a slow disk only stalls this client
+        let mut doc = Document::open(path, read_only, force, &self.state_dir)?;
+        // a swap file kept by a session that has the file open holds its live edits, not abandoned ones
+        if doc.recovery_available() && self.open_elsewhere(path, session_token){
+            doc.share_swap();
+        }
+        lock(&self.documents).insert(session_token.to_string(), Arc::new(Mutex::new(doc)));
+
+        Ok(())
+    }
+    pub fn close_document(&self, session_token: &str){
+        lock(&self.orphaned).remove(session_token);
+        let doc = lock(&self.documents).remove(session_token);
+        if let Some(doc) = doc{
+            if let Err(e) = lock(&doc).remove_swap(){
+                log::warn!("{}: failed to remove swap file. error: {}", session_id(session_token), e);
+            }
+        }
+    }
+    /// Replaces the text of the session's document with that stored in its swap file. refused with 
+    /// EditorError::FileOpenElsewhere while another session has the file open
+    pub fn recover_swap(&self, session_token: &str) -> Result<(), EditorError>{
+        let doc = self.document(session_token).ok_or(EditorError::NoDocumentOpen)?;
+        self.refuse_if_open_elsewhere(&doc, session_token)?;
+        let mut doc = lock(&doc);
+        doc.recover_swap()
+    }
+    /// Deletes the swap file of the session's document. refused with EditorError::FileOpenElsewhere while another 
+    /// session has the file open, since the swap file may then hold that session's unsaved changes
+    pub fn discard_swap(&self, session_token: &str) -> Result<(), EditorError>{
+        let doc = self.document(session_token).ok_or(EditorError::NoDocumentOpen)?;
+        self.refuse_if_open_elsewhere(&doc, session_token)?;
+        let mut doc = lock(&doc);
+        doc.discard_swap()
+    }
+    fn refuse_if_open_elsewhere(&self, doc: &Mutex<Document>, session_token: &str) -> Result<(), EditorError>{
+        let file_name = lock(doc).file_name();
+        match file_name{
+            Some(file_name) if self.open_elsewhere(Path::new(&file_name), session_token) => {
+                Err(EditorError::FileOpenElsewhere{path: PathBuf::from(file_name)})
+            }
+            _ => Ok(()),
+        }
+    }
+    // whether a session other than session_token has the file at path open. paths are compared by swap file, 
+    // which resolves them to the same absolute path
+    fn open_elsewhere(&self, path: &Path, session_token: &str) -> bool{
+        let swap_path = swap::swap_path(&self.state_dir, path);
+        self.documents().into_iter()
+            .filter(|(other_session, _)| other_session != session_token)
+            .any(|(_, doc)| lock(&doc).swap_path() == Some(swap_path.as_path()))
+    }
+    /// Drops a client's document without touching its swap file, for when the document can no longer be trusted
+    pub fn discard_document(&self, session_token: &str){
+        lock(&self.orphaned).remove(session_token);
+        lock(&self.documents).remove(session_token);
+    }
+    /// Readies every document for the server exiting. modified documents are saved first if save is set. any still modified 
+    /// afterwards refuse the shutdown, unless force is set, in which case their changes are kept in swap files for recovery
+    pub fn prepare_shutdown(&self, force: bool, save: bool) -> Result<(), EditorError>{
+        let mut unsaved = Vec::new();
+        for (session_token, doc) in self.documents(){
+            let mut doc = lock(&doc);
+            if save && doc.is_modified(){
+                if let Err(e) = doc.save(){
+                    log::error!("{}: failed to save before shutdown. error: {}", session_id(&session_token), e);
+                }
+            }
+            if doc.is_modified(){
+                unsaved.push(doc.file_name().unwrap_or_else(|| session_id(&session_token)));
+            }
+        }
+
+        if unsaved.is_empty(){
+            Ok(())
+        }else if force{
+            self.write_swap_files();
+            Ok(())
+        }else{
+            Err(EditorError::UnsavedChanges{file_names: unsaved})
+        }
+    }
+    /// Marks a client's document as abandoned, for when the client went away without closing it. 
+    /// does nothing if the client has no document open
+    pub fn orphan_document(&self, session_token: &str){
+        if self.document(session_token).is_some(){
+            lock(&self.orphaned).insert(session_token.to_string(), Instant::now());
         }
+    }
+    /// Drops documents orphaned for longer than grace_period. unsaved changes are kept in swap files for recovery
+    pub fn drop_expired_orphans(&self, grace_period: Duration){
+        let expired: Vec<String> = {
+            let mut orphaned = lock(&self.orphaned);
+            let expired = orphaned.iter()
+                .filter(|(_, orphaned_at)| orphaned_at.elapsed() >= grace_period)
+                .map(|(session_token, _)| session_token.clone())
+                .collect::<Vec<String>>();
+            orphaned.retain(|session_token, _| !expired.contains(session_token));
+            expired
+        };
 
-        None
+        for session_token in expired{
+            let doc = lock(&self.documents).remove(&session_token);
+            if let Some(doc) = doc{
+                let doc = lock(&doc);
+                log::info!("{}: dropping abandoned {}", session_id(&session_token), doc.file_name().unwrap_or_default());
+                if let Err(e) = doc.write_swap(){
+                    log::error!("{}: failed to write swap file. error: {}", session_id(&session_token), e);
+                }
+            }
+        }
     }
-    pub fn document_mut(&mut self, client_address: &str) -> Option<&mut Document>{
-        if let Some(doc) = self.documents.get_mut(client_address){
-            return Some(doc);
+    /// Attaches the session a client presents to the connection it is now using, replacing current_session. 
+    /// a document left open in current_session is orphaned
+    pub fn resume_session(&self, session_token: &str, current_session: &str) -> Result<(), EditorError>{
+        if self.document(session_token).is_none(){
+            return Err(EditorError::UnknownSession);
+        }
+        if session_token != current_session{
+            self.orphan_document(current_session);
         }
+        lock(&self.orphaned).remove(session_token);
 
-        None
+        Ok(())
+    }
+    /// Records the session's document, and where the client was in it, in the session file called name
+    pub fn save_session(&self, name: &str, session_token: &str) -> Result<(), EditorError>{
+        let doc = self.document(session_token).ok_or(EditorError::NoDocumentOpen)?;
+        let state = lock(&doc).session_state();
+        // a session only has one document, for now. the file format allows for more
+        let session_file = SessionFile{focused: state.as_ref().map(|_| 0), documents: state.into_iter().collect()};
+        session::write(&self.state_dir, name, &session_file)
     }
-    pub fn open_document(&mut self, path: &PathBuf, client_address: &str) -> Result<(), Box<dyn Error>>{
-        let doc = Document::open(path)?;
-        self.documents.insert(client_address.to_string(), doc);
+    /// Opens the focused document from the session file called name, replacing the session's current document, and 
+    /// restores where the client was in it. refused with EditorError::UnsavedChanges while the current document has 
+    /// unsaved changes, which replacing it would lose
+    pub fn load_session(&self, name: &str, session_token: &str) -> Result<(), EditorError>{
+        if let Some(doc) = self.document(session_token){
+            let doc = lock(&doc);
+            if doc.is_modified(){
+                return Err(EditorError::UnsavedChanges{file_names: vec![doc.file_name().unwrap_or_else(|| session_id(session_token))]});
+            }
+        }
+        let session_file = session::read(&self.state_dir, name)?;
+        let state = session_file.focused
+            .and_then(|focused| session_file.documents.get(focused))
+            .ok_or(EditorError::NoDocumentOpen)?;
+        self.open_document(&state.file_path, false, false, session_token)?;
+        if let Some(doc) = self.document(session_token){
+            lock(&doc).restore_session_state(state);
+        }
 
         Ok(())
     }
-    pub fn close_document(&mut self, client_address: &str){
-        if self.documents.contains_key(client_address){
-            self.documents.remove(client_address);
+    // a snapshot of the registry, so documents can be locked one at a time without holding the registry lock
+    fn documents(&self) -> Vec<(String, Arc<Mutex<Document>>)>{
+        lock(&self.documents).iter()
+            .map(|(session_token, doc)| (session_token.clone(), Arc::clone(doc)))
+            .collect()
+    }
+    /// Persists every modified document's unsaved state to its swap file
+    pub fn write_swap_files(&self){
+        for (session_token, doc) in self.documents(){
+            if let Err(e) = lock(&doc).write_swap(){
+                log::error!("{}: failed to write swap file. error: {}", session_id(&session_token), e);
+            }
+        }
+    }
+}
+
+/// An unguessable token identifying a new session. it is all a client needs to take over the session's document
+pub fn new_session_token() -> String{
+    let mut bytes = [0u8; 16];
+    if File::open("/dev/urandom").and_then(|mut random| random.read_exact(&mut bytes)).is_err(){
+        // not unguessable, but still unique. the server only listens on localhost
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        bytes[..8].copy_from_slice(&nanos.to_le_bytes());
+        bytes[8..].copy_from_slice(&COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    }
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A short name for a session, for logs and error messages. the token is a credential, so it is never written out. 
+/// the name is a truncated hash, which can not be turned back into the token
+pub fn session_id(session_token: &str) -> String{
+    let mut hasher = DefaultHasher::new();
+    session_token.hash(&mut hasher);
+    format!("session {:08x}", hasher.finish() >> 32)
+}
+
+/// Locks mutex, even if a client thread panicked while holding it. a panicking client's document is discarded, so 
+/// poisoning only tells us about a failure that has already been contained
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T>{
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Splits a trailing :line or :line:column, as printed by compilers and grep, from a path that does not exist as written. 
+/// the returned line and column are converted from 1 based to 0 based
+pub fn parse_file_location(file_path: &Path) -> (PathBuf, Option<usize>, Option<usize>){
+    if file_path.exists(){
+        return (file_path.to_path_buf(), None, None);
+    }
+
+    let text = file_path.to_string_lossy();
+    // grep -n and some compilers leave a trailing separator before the message
+    let text = text.strip_suffix(':').unwrap_or(&text);
+    if let Some((rest, last)) = text.rsplit_once(':'){
+        if let Ok(last) = last.parse::<usize>(){
+            if let Some((path, line)) = rest.rsplit_once(':'){
+                if let Ok(line) = line.parse::<usize>(){
+                    return (PathBuf::from(path), Some(line.saturating_sub(1)), Some(last.saturating_sub(1)));
+                }
+            }
+            return (PathBuf::from(rest), Some(last.saturating_sub(1)), None);
         }
     }
+
+    (file_path.to_path_buf(), None, None)
+}
+
+
+
+
+
+#[test]
+fn parse_file_location_leaves_plain_paths_alone(){
+    assert!(parse_file_location(Path::new("src/idk.rs")) == (PathBuf::from("src/idk.rs"), None, None));
+}
+#[test]
+fn parse_file_location_splits_line(){
+    assert!(parse_file_location(Path::new("src/idk.rs:12")) == (PathBuf::from("src/idk.rs"), Some(11), None));
+}
+#[test]
+fn parse_file_location_splits_line_and_column(){
+    assert!(parse_file_location(Path::new("src/idk.rs:12:5")) == (PathBuf::from("src/idk.rs"), Some(11), Some(4)));
+}
+#[test]
+fn parse_file_location_ignores_trailing_separator(){
+    assert!(parse_file_location(Path::new("src/idk.rs:12:5:")) == (PathBuf::from("src/idk.rs"), Some(11), Some(4)));
+}
+#[test]
+fn lock_recovers_from_poisoned_mutex(){
+    let mutex = Arc::new(Mutex::new(0));
+    let poisoner = Arc::clone(&mutex);
+    let _ = std::thread::spawn(move ||{
+        let _guard = poisoner.lock().unwrap();
+        panic!("poisoning mutex");
+    }).join();
+    assert!(mutex.is_poisoned());
+    *lock(&mutex) += 1;
+    assert!(*lock(&mutex) == 1);
+}
+#[test]
+fn prepare_shutdown_refuses_unsaved_changes_unless_saved(){
+    let path = std::env::temp_dir().join(format!("nlo_shutdown_{}.txt", std::process::id()));
+    let state_dir = std::env::temp_dir().join(format!("nlo_shutdown_state_{}", std::process::id()));
+    std::fs::write(&path, "idk\n").unwrap();
+    let editor = Editor::new(state_dir.clone());
+    editor.open_document(&path, false, false, "client").unwrap();
+    lock(&editor.document("client").unwrap()).insert_char('x');
+
+    assert!(editor.prepare_shutdown(false, false).is_err());
+    assert!(editor.prepare_shutdown(false, true).is_ok());
+    assert!(std::fs::read_to_string(&path).unwrap() == "xidk\n");
+    std::fs::remove_file(&path).unwrap();
+}
+#[test]
+fn expired_orphans_are_dropped_with_unsaved_changes_in_swap_files(){
+    let path = std::env::temp_dir().join(format!("nlo_orphan_{}.txt", std::process::id()));
+    let state_dir = std::env::temp_dir().join(format!("nlo_orphan_state_{}", std::process::id()));
+    std::fs::write(&path, "idk\n").unwrap();
+    let editor = Editor::new(state_dir.clone());
+    editor.open_document(&path, false, false, "client").unwrap();
+    lock(&editor.document("client").unwrap()).insert_char('x');
+
+    editor.orphan_document("client");
+    editor.drop_expired_orphans(ORPHAN_GRACE_PERIOD);
+    assert!(editor.document("client").is_some());
+    editor.drop_expired_orphans(Duration::ZERO);
+    assert!(editor.document("client").is_none());
+    let swap_path = swap::swap_path(&state_dir, &path);
+    assert!(swap::read(&swap_path).unwrap().text == "xidk\n");
+
+    std::fs::remove_dir_all(&state_dir).unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+#[test]
+fn new_session_tokens_are_unique(){
+    assert!(new_session_token().len() == 32);
+    assert!(new_session_token() != new_session_token());
+}
+#[test]
+fn resume_session_reclaims_orphaned_document(){
+    let path = std::env::temp_dir().join(format!("nlo_resume_{}.txt", std::process::id()));
+    let state_dir = std::env::temp_dir().join(format!("nlo_resume_state_{}", std::process::id()));
+    std::fs::write(&path, "idk\n").unwrap();
+    let editor = Editor::new(state_dir.clone());
+    editor.open_document(&path, false, false, "old").unwrap();
+    editor.orphan_document("old");
+
+    assert!(editor.resume_session("missing", "new") == Err(EditorError::UnknownSession));
+    assert!(editor.resume_session("old", "new").is_ok());
+    editor.drop_expired_orphans(Duration::ZERO);
+    assert!(editor.document("old").is_some());
+    std::fs::remove_file(&path).unwrap();
+}
+#[test]
+fn load_session_refuses_to_replace_unsaved_changes(){
+    let path = std::env::temp_dir().join(format!("nlo_load_session_{}.txt", std::process::id()));
+    let state_dir = std::env::temp_dir().join(format!("nlo_load_session_state_{}", std::process::id()));
+    std::fs::write(&path, "idk\n").unwrap();
+    let editor = Editor::new(state_dir.clone());
+    editor.open_document(&path, false, false, "client").unwrap();
+    lock(&editor.document("client").unwrap()).insert_char('x');
+
+    assert!(matches!(editor.load_session("idk", "client"), Err(EditorError::UnsavedChanges{..})));
+    assert!(lock(&editor.document("client").unwrap()).is_modified());
+    std::fs::remove_file(&path).unwrap();
+}
+#[test]
+fn swap_file_of_a_file_open_in_another_session_is_not_recoverable(){
+    let path = std::env::temp_dir().join(format!("nlo_shared_swap_{}.txt", std::process::id()));
+    let state_dir = std::env::temp_dir().join(format!("nlo_shared_swap_state_{}", std::process::id()));
+    std::fs::write(&path, "idk\n").unwrap();
+    let editor = Editor::new(state_dir.clone());
+    editor.open_document(&path, false, false, "first").unwrap();
+    lock(&editor.document("first").unwrap()).insert_char('x');
+    editor.write_swap_files();
+
+    editor.open_document(&path, false, false, "second").unwrap();
+    assert!(!lock(&editor.document("second").unwrap()).recovery_available());
+    assert!(matches!(editor.discard_swap("second"), Err(EditorError::FileOpenElsewhere{..})));
+    assert!(matches!(editor.recover_swap("second"), Err(EditorError::FileOpenElsewhere{..})));
+    let swap_path = swap::swap_path(&state_dir, &path);
+    assert!(swap::read(&swap_path).unwrap().text == "xidk\n");
+
+    std::fs::remove_dir_all(&state_dir).unwrap();
+    std::fs::remove_file(&path).unwrap();
 }
@@ -0,0 +1,259 @@
+use serde::{Serialize, Deserialize};
+use crate::error::EditorError;
+use ropey::RopeSlice;
+
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF16BE_BOM: [u8; 2] = [0xFE, 0xFF];
+/// How much of a file is inspected when deciding whether it is binary
+pub const BINARY_SNIFF_LENGTH: usize = 8192;
+
+
+
+/// The character encoding a document was read from, and will be written back to
+#[derive(PartialEq, Eq, Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub enum Encoding{
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// single byte fallback. every byte maps directly to the char with the same value, so decoding can not fail
+    Latin1,
+}
+
+/// Determines the encoding of raw file contents, and whether they begin with a byte order mark
+pub fn detect(bytes: &[u8]) -> (Encoding, bool){
+    if bytes.starts_with(&UTF8_BOM){
+        return (Encoding::Utf8, true);
+    }
+    if bytes.starts_with(&UTF16LE_BOM){
+        return (Encoding::Utf16Le, true);
+    }
+    if bytes.starts_with(&UTF16BE_BOM){
+        return (Encoding::Utf16Be, true);
+    }
+
+    if let Some(encoding) = detect_utf16_without_bom(bytes){
+        return (encoding, false);
+    }
+    if std::str::from_utf8(bytes).is_ok(){
+        return (Encoding::Utf8, false);
+    }
+
+    (Encoding::Latin1, false)
+}
+
+// mostly ascii utf16 text has a zero byte in every code unit. which half of the code unit it is in gives away the byte order
+fn detect_utf16_without_bom(bytes: &[u8]) -> Option<Encoding>{
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2){return None;}
+
+    let code_units = bytes.len() / 2;
+    let mut zeros_at_even = 0;
+    let mut zeros_at_odd = 0;
+    for pair in bytes.chunks_exact(2){
+        if pair[0] == 0{zeros_at_even += 1;}
+        if pair[1] == 0{zeros_at_odd += 1;}
+    }
+
+    if zeros_at_odd > code_units / 2 && zeros_at_even == 0{
+        Some(Encoding::Utf16Le)
+    }else if zeros_at_even > code_units / 2 && zeros_at_odd == 0{
+        Some(Encoding::Utf16Be)
+    }else{
+        None
+    }
+}
+
+/// Guesses whether raw file contents are binary data rather than text, by inspecting the first chunk for zero bytes and 
+/// control characters that are not valid text in any supported encoding
+pub fn is_binary(bytes: &[u8]) -> bool{
+    let chunk = &bytes[..bytes.len().min(BINARY_SNIFF_LENGTH)];
+    match detect(chunk){
+        (Encoding::Utf16Le | Encoding::Utf16Be, _) => return false,
+        (_, true) => return false,
+        _ => {}
+    }
+    if chunk.contains(&0){
+        return true;
+    }
+
+    match std::str::from_utf8(chunk){
+        Ok(_) => false,
+        // the chunk boundary may have split a multi byte sequence, which is still valid text
+        Err(e) if e.error_len().is_none() => false,
+        Err(_) => {
+            // invalid utf8 could still be single byte text. real text has few control characters
+            let control_characters = chunk.iter().filter(|&&byte| is_control_character(byte)).count();
+            control_characters * 10 > chunk.len()
+        }
+    }
+}
+
+fn is_control_character(byte: u8) -> bool{
+    (byte < 0x20 && !matches!(byte, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)) || byte == 0x7F
+}
+
+/// Decodes raw file contents into a string, skipping the byte order mark if one is present
+pub fn decode(bytes: &[u8], encoding: Encoding, has_bom: bool) -> Result<String, EditorError>{
+    let bytes = if has_bom{&bytes[bom(encoding).len()..]}else{bytes};
+
+    match encoding{
+        Encoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|_| EditorError::InvalidText{encoding}),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            if !bytes.len().is_multiple_of(2){
+                return Err(EditorError::InvalidText{encoding});
+            }
+            let code_units: Vec<u16> = bytes.chunks_exact(2).map(|pair| {
+                if encoding == Encoding::Utf16Le{
+                    u16::from_le_bytes([pair[0], pair[1]])
+                }else{
+                    u16::from_be_bytes([pair[0], pair[1]])
+                }
+            }).collect();
+            String::from_utf16(&code_units).map_err(|_| EditorError::InvalidText{encoding})
+        }
+        Encoding::Latin1 => Ok(bytes.iter().map(|&byte| byte as char).collect()),
+    }
+}
+
+/// Encodes document text to raw bytes, prepending a byte order mark if requested
+pub fn encode(text: RopeSlice, encoding: Encoding, has_bom: bool) -> Result<Vec<u8>, EditorError>{
+    let mut bytes = Vec::with_capacity(text.len_bytes());
+    if has_bom{
+        bytes.extend_from_slice(bom(encoding));
+    }
+
+    match encoding{
+        Encoding::Utf8 => {
+            for chunk in text.chunks(){
+                bytes.extend_from_slice(chunk.as_bytes());
+            }
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let mut buffer = [0u16; 2];
+            for char in text.chars(){
+                for code_unit in char.encode_utf16(&mut buffer){
+                    if encoding == Encoding::Utf16Le{
+                        bytes.extend_from_slice(&code_unit.to_le_bytes());
+                    }else{
+                        bytes.extend_from_slice(&code_unit.to_be_bytes());
+                    }
+                }
+            }
+        }
+        Encoding::Latin1 => {
+            for char in text.chars(){
+                match u8::try_from(char){
+                    Ok(byte) => bytes.push(byte),
+                    Err(_) => return Err(EditorError::UnencodableCharacter{encoding, character: char}),
+                }
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn bom(encoding: Encoding) -> &'static [u8]{
+    match encoding{
+        Encoding::Utf8 => &UTF8_BOM,
+        Encoding::Utf16Le => &UTF16LE_BOM,
+        Encoding::Utf16Be => &UTF16BE_BOM,
+        Encoding::Latin1 => &[],
+    }
+}
+
+
+
+
+
+#[cfg(test)]
+use ropey::Rope;
+
+//DETECT
+#[test]
+fn detect_recognizes_utf8_without_bom(){
+    assert!(detect("idk\nsömething\n".as_bytes()) == (Encoding::Utf8, false));
+}
+#[test]
+fn detect_recognizes_utf8_with_bom(){
+    assert!(detect(&[0xEF, 0xBB, 0xBF, b'i', b'd', b'k']) == (Encoding::Utf8, true));
+}
+#[test]
+fn detect_recognizes_utf16le_with_bom(){
+    assert!(detect(&[0xFF, 0xFE, b'i', 0, b'd', 0]) == (Encoding::Utf16Le, true));
+}
+#[test]
+fn detect_recognizes_utf16be_with_bom(){
+    assert!(detect(&[0xFE, 0xFF, 0, b'i', 0, b'd']) == (Encoding::Utf16Be, true));
+}
+#[test]
+fn detect_recognizes_utf16le_without_bom(){
+    assert!(detect(&[b'i', 0, b'd', 0, b'k', 0]) == (Encoding::Utf16Le, false));
+}
+#[test]
+fn detect_falls_back_to_latin1_when_not_valid_utf8(){
+    assert!(detect(&[b'c', b'a', b'f', 0xE9]) == (Encoding::Latin1, false));
+}
+
+//IS BINARY
+#[test]
+fn is_binary_detects_zero_bytes(){
+    assert!(is_binary(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0]));
+}
+#[test]
+fn is_binary_detects_invalid_sequences_full_of_control_characters(){
+    assert!(is_binary(&[0xCF, 0xFA, 0xED, 0xFE, 0x07, 0x01, 0x03, 0x02, 0x04, 0x05]));
+}
+#[test]
+fn is_binary_accepts_text(){
+    assert!(!is_binary("idk\n\tsömething\r\n".as_bytes()));
+}
+#[test]
+fn is_binary_accepts_latin1_text(){
+    assert!(!is_binary(&[b'c', b'a', b'f', 0xE9, b'\n']));
+}
+#[test]
+fn is_binary_accepts_utf16_text(){
+    assert!(!is_binary(&[0xFF, 0xFE, b'i', 0, b'd', 0, b'k', 0]));
+}
+
+//DECODE
+#[test]
+fn decode_strips_bom(){
+    let decoded = decode(&[0xEF, 0xBB, 0xBF, b'i', b'd', b'k'], Encoding::Utf8, true).unwrap();
+    assert!(decoded == "idk");
+}
+#[test]
+fn decode_utf16be_works(){
+    let decoded = decode(&[0xFE, 0xFF, 0, b'i', 0, b'd', 0, b'k'], Encoding::Utf16Be, true).unwrap();
+    assert!(decoded == "idk");
+}
+#[test]
+fn decode_latin1_works(){
+    let decoded = decode(&[b'c', b'a', b'f', 0xE9], Encoding::Latin1, false).unwrap();
+    assert!(decoded == "café");
+}
+#[test]
+fn decode_utf16_with_odd_byte_count_fails(){
+    assert!(decode(&[b'i', 0, b'd'], Encoding::Utf16Le, false).is_err());
+}
+
+//ENCODE
+#[test]
+fn encode_round_trips_utf16le_with_bom(){
+    let bytes = [0xFF, 0xFE, b'i', 0, b'd', 0, b'\n', 0];
+    let text = Rope::from(decode(&bytes, Encoding::Utf16Le, true).unwrap());
+    assert!(encode(text.slice(..), Encoding::Utf16Le, true).unwrap() == bytes);
+}
+#[test]
+fn encode_round_trips_latin1(){
+    let bytes = [b'c', b'a', b'f', 0xE9];
+    let text = Rope::from(decode(&bytes, Encoding::Latin1, false).unwrap());
+    assert!(encode(text.slice(..), Encoding::Latin1, false).unwrap() == bytes);
+}
+#[test]
+fn encode_latin1_fails_for_chars_outside_latin1(){
+    let text = Rope::from("€");
+    assert!(encode(text.slice(..), Encoding::Latin1, false).is_err());
+}
@@ -0,0 +1,123 @@
+use crate::encoding::Encoding;
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+
+/// Why a request failed. sent to clients in ServerResponse::Failed, so they can react to the specific failure.
+/// the variant name is the machine readable code, and the fields are its context
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum EditorError{
+    /// The request needs a document, and the client has not opened one
+    NoDocumentOpen,
+    /// A file system operation failed. message is the os error, for display
+    Io{path: PathBuf, kind: IoErrorKind, message: String},
+    /// The requested file looks like binary data. it can still be opened by setting force in OpenFile
+    FileIsBinary{path: PathBuf},
+    /// Edits and saves are refused for read only documents
+    ReadOnly{path: Option<PathBuf>},
+    /// The file contents are not valid text in the detected encoding
+    InvalidText{encoding: Encoding},
+    /// The document contains a character the requested encoding has no representation for
+    UnencodableCharacter{encoding: Encoding, character: char},
+    /// Files at least LARGE_FILE_THRESHOLD bytes long can only be opened in an ascii compatible encoding
+    LargeFileEncodingUnsupported{path: PathBuf, encoding: Encoding},
+    /// A swap file exists, but could not be read or written
+    InvalidSwapFile{path: PathBuf, message: String},
+    /// RecoverSwap or DiscardSwap was requested, but the document has no swap file awaiting recovery
+    NoSwapFile,
+    /// RecoverSwap or DiscardSwap was refused, because another session has the file open, and the swap file may hold its unsaved changes
+    FileOpenElsewhere{path: PathBuf},
+    /// Resume presented a session token that has no document. it may have expired, or never existed
+    UnknownSession,
+    /// Session names must be plain file names
+    InvalidSessionName{name: String},
+    /// A session file exists, but could not be read or written
+    InvalidSessionFile{path: PathBuf, message: String},
+    /// Shutdown, or LoadSession, was refused, because these documents have unsaved changes
+    UnsavedChanges{file_names: Vec<String>},
+    /// The request could not be parsed. message is the parse error. the connection stays open
+    MalformedRequest{message: String},
+    /// The request names an action this server does not know, likely sent by a newer client. the connection stays open
+    UnknownAction{name: String},
+    /// The request was longer than MESSAGE_SIZE bytes, and was discarded without being parsed
+    RequestTooLarge{size: usize, limit: usize},
+    /// The server failed while handling the request. the connection is closed afterwards
+    Internal{message: String},
+}
+impl EditorError{
+    /// Wraps an io error with the path it happened at
+    pub fn io(path: &Path, error: io::Error) -> Self{
+        EditorError::Io{path: path.to_path_buf(), kind: error.kind().into(), message: error.to_string()}
+    }
+}
+impl fmt::Display for EditorError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            EditorError::NoDocumentOpen => write!(f, "no document open"),
+            EditorError::Io{path, kind: _, message} => write!(f, "{}: {}", path.display(), message),
+            EditorError::FileIsBinary{path} => write!(f, "{} appears to be a binary file", path.display()),
+            EditorError::ReadOnly{path: Some(path)} => write!(f, "{} is read only", path.display()),
+            EditorError::ReadOnly{path: None} => write!(f, "document is read only"),
+            EditorError::InvalidText{encoding} => write!(f, "file is not valid {:?} text", encoding),
+            EditorError::UnencodableCharacter{encoding, character} => write!(f, "'{}' can not be represented in {:?}", character, encoding),
+            EditorError::LargeFileEncodingUnsupported{path, encoding} => write!(f, "{} is too large to open as {:?}", path.display(), encoding),
+            EditorError::InvalidSwapFile{path, message} => write!(f, "invalid swap file {}: {}", path.display(), message),
+            EditorError::NoSwapFile => write!(f, "no swap file to recover"),
+            EditorError::FileOpenElsewhere{path} => write!(f, "{} is open in another session", path.display()),
+            EditorError::UnknownSession => write!(f, "unknown session"),
+            EditorError::InvalidSessionName{name} => write!(f, "invalid session name: {}", name),
+            EditorError::InvalidSessionFile{path, message} => write!(f, "invalid session file {}: {}", path.display(), message),
+            EditorError::UnsavedChanges{file_names} => write!(f, "unsaved changes in {}", file_names.join(", ")),
+            EditorError::MalformedRequest{message} => write!(f, "malformed request: {}", message),
+            EditorError::UnknownAction{name} => write!(f, "unknown action: {}", name),
+            EditorError::RequestTooLarge{size, limit} => write!(f, "request of {} bytes is larger than the {} byte limit", size, limit),
+            EditorError::Internal{message} => write!(f, "internal server error: {}", message),
+        }
+    }
+}
+impl std::error::Error for EditorError{}
+
+/// A serializable mirror of the io::ErrorKinds a client might want to act on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum IoErrorKind{
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    IsADirectory,
+    StorageFull,
+    ReadOnlyFilesystem,
+    InvalidData,
+    Other,
+}
+impl From<io::ErrorKind> for IoErrorKind{
+    fn from(kind: io::ErrorKind) -> Self{
+        match kind{
+            io::ErrorKind::NotFound => IoErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => IoErrorKind::PermissionDenied,
+            io::ErrorKind::AlreadyExists => IoErrorKind::AlreadyExists,
+            io::ErrorKind::IsADirectory => IoErrorKind::IsADirectory,
+            io::ErrorKind::StorageFull => IoErrorKind::StorageFull,
+            io::ErrorKind::ReadOnlyFilesystem => IoErrorKind::ReadOnlyFilesystem,
+            io::ErrorKind::InvalidData => IoErrorKind::InvalidData,
+            _ => IoErrorKind::Other,
+        }
+    }
+}
+
+
+
+
+
+#[test]
+fn io_error_keeps_path_and_kind(){
+    let error = EditorError::io(Path::new("idk.rs"), io::Error::from(io::ErrorKind::PermissionDenied));
+    assert!(matches!(error, EditorError::Io{ref path, kind: IoErrorKind::PermissionDenied, ..} if path == Path::new("idk.rs")));
+}
+#[test]
+fn error_round_trips_through_ron(){
+    let error = EditorError::UnencodableCharacter{encoding: Encoding::Latin1, character: '€'};
+    assert!(ron::from_str::<EditorError>(&ron::to_string(&error).unwrap()).unwrap() == error);
+}
@@ -0,0 +1,142 @@
+use serde::{Serialize, Deserialize};
+use ropey::RopeSlice;
+
+/// The indent width used when a document's indentation can not be detected
+pub const DEFAULT_INDENT_WIDTH: usize = 4;
+/// Only this many lines are examined when detecting a document's indentation
+const DETECTION_LINE_LIMIT: usize = 1000;
+
+
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum IndentStyle{
+    #[default]
+    Spaces,
+    Tabs,
+}
+
+/// How a document is indented. width is the number of spaces in one level of indentation, and the number of columns
+/// a tab character is displayed as
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Indent{
+    pub style: IndentStyle,
+    pub width: usize,
+}
+impl Default for Indent{
+    fn default() -> Self{
+        Self{style: IndentStyle::Spaces, width: DEFAULT_INDENT_WIDTH}
+    }
+}
+impl Indent{
+    /// The text inserted for one level of indentation
+    pub fn unit(&self) -> String{
+        match self.style{
+            IndentStyle::Tabs => String::from("\t"),
+            IndentStyle::Spaces => " ".repeat(self.width),
+        }
+    }
+}
+
+/// Guesses how text is indented, from the leading whitespace of its lines. the style is whichever lines are more
+/// often indented with, and the width is the most common change in indentation between consecutive space indented lines
+pub fn detect(text: RopeSlice) -> Option<Indent>{
+    let mut tab_indented_lines = 0;
+    let mut space_indented_lines = 0;
+    // how often each change in indentation, from 1 to 8 spaces, occurs
+    let mut width_counts = [0usize; 9];
+    let mut previous_spaces = 0;
+    for line in text.lines().take(DETECTION_LINE_LIMIT){
+        let mut chars = line.chars().peekable();
+        match chars.peek(){
+            Some('\t') => {
+                tab_indented_lines += 1;
+                continue;
+            }
+            // blank lines say nothing about indentation
+            Some('\n') | Some('\r') | None => continue,
+            _ => {}
+        }
+        let spaces = line.chars().take_while(|char| *char == ' ').count();
+        if spaces > 0{
+            space_indented_lines += 1;
+        }
+        let change = spaces.abs_diff(previous_spaces);
+        if change > 0 && change < width_counts.len(){
+            width_counts[change] += 1;
+        }
+        previous_spaces = spaces;
+    }
+
+    if tab_indented_lines == 0 && space_indented_lines == 0{
+        None
+    }else if tab_indented_lines > space_indented_lines{
+        Some(Indent{style: IndentStyle::Tabs, width: DEFAULT_INDENT_WIDTH})
+    }else{
+        // the first most common width, so a tie favours the narrower indent
+        let width = (1..width_counts.len())
+            .max_by_key(|width| (width_counts[*width], std::cmp::Reverse(*width)))
+            .filter(|width| width_counts[*width] > 0)
+            .unwrap_or(DEFAULT_INDENT_WIDTH);
+        Some(Indent{style: IndentStyle::Spaces, width})
+    }
+}
+
+/// The display column chars up to char_index end at, with tabs advancing to the next multiple of tab_width
+pub fn display_column(chars: impl Iterator<Item = char>, char_index: usize, tab_width: usize) -> usize{
+    chars.take(char_index).fold(0, |column, char| advance(column, char, tab_width))
+}
+
+/// Replaces tabs with enough spaces to reach the next multiple of tab_width
+pub fn expand_tabs(chars: impl Iterator<Item = char>, tab_width: usize) -> String{
+    let mut expanded = String::new();
+    let mut column = 0;
+    for char in chars{
+        let next_column = advance(column, char, tab_width);
+        if char == '\t'{
+            expanded.extend(std::iter::repeat_n(' ', next_column - column));
+        }else{
+            expanded.push(char);
+        }
+        column = next_column;
+    }
+
+    expanded
+}
+
+fn advance(column: usize, char: char, tab_width: usize) -> usize{
+    if char == '\t'{
+        let tab_width = tab_width.max(1);
+        column + tab_width - (column % tab_width)
+    }else{
+        column + 1
+    }
+}
+
+
+
+
+
+#[test]
+fn detect_finds_space_width(){
+    let text = ropey::Rope::from("fn idk(){\n  if x{\n    y\n  }\n}\n");
+    assert!(detect(text.slice(..)) == Some(Indent{style: IndentStyle::Spaces, width: 2}));
+}
+#[test]
+fn detect_finds_tabs(){
+    let text = ropey::Rope::from("fn idk(){\n\tif x{\n\t\ty\n\t}\n}\n");
+    assert!(detect(text.slice(..)) == Some(Indent{style: IndentStyle::Tabs, width: DEFAULT_INDENT_WIDTH}));
+}
+#[test]
+fn detect_returns_none_without_indentation(){
+    let text = ropey::Rope::from("idk\nsomething\n\nelse\n");
+    assert!(detect(text.slice(..)).is_none());
+}
+#[test]
+fn display_column_expands_tabs(){
+    assert!(display_column("\tab\tc".chars(), 4, 4) == 8);
+    assert!(display_column("a\tb".chars(), 2, 8) == 8);
+}
+#[test]
+fn expand_tabs_pads_to_tab_stops(){
+    assert!(expand_tabs("a\tb\t".chars(), 4) == "a   b   ");
+}
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+
+
+/// A file type, identified by file extension, and the comment syntax ToggleComment uses for it
+#[derive(Debug, PartialEq)]
+pub struct Language{
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    /// the token starting a comment that runs to the end of the line
+    pub line_comment: Option<&'static str>,
+    /// the tokens opening and closing a comment. only used when the language has no line comment
+    pub block_comment: Option<(&'static str, &'static str)>,
+}
+
+const LANGUAGES: &[Language] = &[
+    Language{name: "rust", extensions: &["rs"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "c", extensions: &["c", "h"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "c++", extensions: &["cpp", "cc", "cxx", "hpp", "hh", "hxx"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "c#", extensions: &["cs"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "go", extensions: &["go"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "java", extensions: &["java"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "kotlin", extensions: &["kt", "kts"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "swift", extensions: &["swift"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "javascript", extensions: &["js", "mjs", "cjs", "jsx"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "typescript", extensions: &["ts", "tsx"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "zig", extensions: &["zig"], line_comment: Some("//"), block_comment: None},
+    Language{name: "ron", extensions: &["ron"], line_comment: Some("//"), block_comment: Some(("/*", "*/"))},
+    Language{name: "python", extensions: &["py", "pyw"], line_comment: Some("#"), block_comment: None},
+    Language{name: "ruby", extensions: &["rb"], line_comment: Some("#"), block_comment: None},
+    Language{name: "perl", extensions: &["pl", "pm"], line_comment: Some("#"), block_comment: None},
+    Language{name: "r", extensions: &["r"], line_comment: Some("#"), block_comment: None},
+    Language{name: "shell", extensions: &["sh", "bash", "zsh", "fish"], line_comment: Some("#"), block_comment: None},
+    Language{name: "toml", extensions: &["toml"], line_comment: Some("#"), block_comment: None},
+    Language{name: "yaml", extensions: &["yml", "yaml"], line_comment: Some("#"), block_comment: None},
+    Language{name: "lua", extensions: &["lua"], line_comment: Some("--"), block_comment: Some(("--[[", "]]"))},
+    Language{name: "sql", extensions: &["sql"], line_comment: Some("--"), block_comment: Some(("/*", "*/"))},
+    Language{name: "haskell", extensions: &["hs"], line_comment: Some("--"), block_comment: Some(("{-", "-}"))},
+    Language{name: "lisp", extensions: &["lisp", "el", "clj", "scm"], line_comment: Some(";"), block_comment: None},
+    Language{name: "ini", extensions: &["ini"], line_comment: Some(";"), block_comment: None},
+    Language{name: "html", extensions: &["html", "htm", "xml", "svg"], line_comment: None, block_comment: Some(("<!--", "-->"))},
+    Language{name: "markdown", extensions: &["md", "markdown"], line_comment: None, block_comment: Some(("<!--", "-->"))},
+    Language{name: "css", extensions: &["css"], line_comment: None, block_comment: Some(("/*", "*/"))},
+];
+
+/// The language of the file at path, from its extension. None for extensions not in the table
+pub fn from_path(path: &Path) -> Option<&'static Language>{
+    let extension = path.extension()?.to_str()?;
+    LANGUAGES.iter().find(|language| {
+        language.extensions.iter().any(|candidate| candidate.eq_ignore_ascii_case(extension))
+    })
+}
+
+
+
+
+
+#[test]
+fn from_path_matches_extension(){
+    assert!(from_path(Path::new("src/idk.rs")).unwrap().name == "rust");
+    assert!(from_path(Path::new("idk.PY")).unwrap().line_comment == Some("#"));
+    assert!(from_path(Path::new("idk.css")).unwrap().line_comment.is_none());
+    assert!(from_path(Path::new("idk")).is_none());
+    assert!(from_path(Path::new("idk.unknown")).is_none());
+}
@@ -0,0 +1,126 @@
+use crate::encoding::Encoding;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Files at least this many bytes long are opened in large file mode, instead of being read into a rope
+pub const LARGE_FILE_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const INDEX_CHUNK_SIZE: usize = 1024 * 1024;
+
+
+
+/// A read only view of a file too large to hold in memory. only the byte offset of each line is kept, and line text is
+/// read from disk on demand
+pub struct LargeFile{
+    path: PathBuf,
+    /// the byte offset each line starts at
+    line_offsets: Vec<u64>,
+    length: u64,
+    /// must be ascii compatible, so lines can be split on newline bytes
+    encoding: Encoding,
+    has_bom: bool,
+}
+impl LargeFile{
+    /// Indexes the line offsets of the file at path, reading it one chunk at a time
+    pub fn open(path: &Path, encoding: Encoding, has_bom: bool) -> io::Result<Self>{
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut chunk = vec![0u8; INDEX_CHUNK_SIZE];
+        let mut line_offsets = vec![0];
+        let mut length = 0;
+        loop{
+            let size = reader.read(&mut chunk)?;
+            if size == 0{break;}
+            for (index, byte) in chunk[..size].iter().enumerate(){
+                if *byte == b'\n'{
+                    line_offsets.push(length + index as u64 + 1);
+                }
+            }
+            length += size as u64;
+        }
+
+        Ok(Self{path: path.to_path_buf(), line_offsets, length, encoding, has_bom})
+    }
+
+    /// Counted the same way ropey counts lines, so a trailing newline begins an empty last line
+    pub fn len_lines(&self) -> usize{
+        self.line_offsets.len()
+    }
+
+    /// Reads up to count lines, starting at the 0 based line number start. newlines are not included
+    pub fn lines(&self, start: usize, count: usize) -> io::Result<Vec<String>>{
+        let end = start.saturating_add(count).min(self.len_lines());
+        if start >= end{
+            return Ok(Vec::new());
+        }
+
+        let start_offset = self.line_offsets[start];
+        let end_offset = self.line_end_offset(end - 1);
+        let mut bytes = vec![0u8; (end_offset - start_offset) as usize];
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start_offset))?;
+        file.read_exact(&mut bytes)?;
+
+        let mut lines = Vec::with_capacity(end - start);
+        for line_number in start..end{
+            let line_start = (self.line_offsets[line_number] - start_offset) as usize;
+            let line_end = (self.line_end_offset(line_number) - start_offset) as usize;
+            let mut line = &bytes[line_start..line_end];
+            if line_number == 0 && self.has_bom && self.encoding == Encoding::Utf8{
+                line = line.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(line);
+            }
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            lines.push(self.decode_line(line));
+        }
+
+        Ok(lines)
+    }
+
+    fn line_end_offset(&self, line_number: usize) -> u64{
+        match self.line_offsets.get(line_number.saturating_add(1)){
+            Some(next_line_start) => *next_line_start,
+            None => self.length,
+        }
+    }
+
+    fn decode_line(&self, line: &[u8]) -> String{
+        match self.encoding{
+            Encoding::Latin1 => line.iter().map(|&byte| byte as char).collect(),
+            _ => String::from_utf8_lossy(line).to_string(),
+        }
+    }
+}
+
+
+
+
+
+#[cfg(test)]
+fn write_test_file(name: &str, contents: &str) -> PathBuf{
+    let path = std::env::temp_dir().join(format!("nlo_large_file_{}_{}.txt", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn len_lines_matches_ropey(){
+    let path = write_test_file("len_lines", "idk\nsomething\nelse\n");
+    let large_file = LargeFile::open(&path, Encoding::Utf8, false).unwrap();
+    assert!(large_file.len_lines() == ropey::Rope::from("idk\nsomething\nelse\n").len_lines());
+    std::fs::remove_file(&path).unwrap();
+}
+#[test]
+fn lines_reads_only_requested_lines(){
+    let path = write_test_file("lines", "idk\nsomething\nelse\n");
+    let large_file = LargeFile::open(&path, Encoding::Utf8, false).unwrap();
+    assert!(large_file.lines(1, 2).unwrap() == vec!["something".to_string(), "else".to_string()]);
+    std::fs::remove_file(&path).unwrap();
+}
+#[test]
+fn lines_past_end_of_file_are_empty(){
+    let path = write_test_file("past_end", "idk\nsomething");
+    let large_file = LargeFile::open(&path, Encoding::Utf8, false).unwrap();
+    assert!(large_file.lines(1, 5).unwrap() == vec!["something".to_string()]);
+    assert!(large_file.lines(5, 5).unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
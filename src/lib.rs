@@ -1,14 +1,37 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use serde::{Serialize, Deserialize};
+use encoding::Encoding;
+use error::EditorError;
+use indent::{Indent, IndentStyle};
 
 pub mod editor;
+pub mod error;
 pub mod document;
+pub mod encoding;
+pub mod indent;
+pub mod language;
+pub mod swap;
+pub mod session;
+pub mod large_file;
+pub mod daemon;
+pub mod logging;
+#[allow(dead_code)]
 mod selection;
+#[allow(dead_code)]
 mod movement;
 
 
 pub const MESSAGE_SIZE: usize = 8192;//4096;
+/// Ends every request and response. ron escapes newlines inside strings, so a serialized message never contains one
+pub const MESSAGE_DELIMITER: u8 = b'\n';
+/// The most unhandled bytes kept for one client. reading stops until requests are handled, and the buffer drains
+pub const READ_BUFFER_LIMIT: usize = 4 * MESSAGE_SIZE;
+pub const SERVER_ADDRESS: &str = "127.0.0.1:7878";
+/// Connections that send nothing for this long are closed. clients left open longer, with nothing to send, should send 
+/// Heartbeat well within it. vanished clients are noticed sooner, by tcp keepalive
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
 
 
 
@@ -22,9 +45,20 @@ pub enum Operation{
 pub enum ServerAction{
     Backspace,
     CloseConnection,
+    /// Reattaches this connection to an earlier session, restoring its document, selections and view. answered with FileOpened
+    Resume{session_token: String},
+    /// Records this session's document, selections and view under name, so they can be loaded after a server restart
+    SaveSession{name: String},
+    /// Opens the document recorded under name, restoring its selections and view. answered with FileOpened
+    LoadSession{name: String},
+    /// Keeps an idle connection from timing out. answered with Acknowledge
+    Heartbeat,
     Delete,
-    GoTo{line_number: usize},
-    OpenFile{file_path: PathBuf},
+    GoTo{line_number: usize, #[serde(default)] column: Option<usize>},
+    /// file_path may end in :line or :line:column, as printed by compilers and grep. those are 1 based, like the tools that print them. line and column are 0 based, like GoTo, and take precedence.
+    /// binary files are refused with EditorError::FileIsBinary, unless force is set
+    /// view_size is the client's (width, height). when given, FileOpened carries the view, already scrolled to the cursor
+    OpenFile{file_path: PathBuf, #[serde(default)] read_only: bool, #[serde(default)] line: Option<usize>, #[serde(default)] column: Option<usize>, #[serde(default)] force: bool, #[serde(default)] view_size: Option<(u16, u16)>},
     UpdateClientViewSize{width: u16, height: u16},
     ScrollClientViewDown{amount: usize},
     ScrollClientViewLeft{amount: usize},
@@ -42,18 +76,164 @@ pub enum ServerAction{
     MoveCursorPageUp,
     InserChar(char),
     InsertNewline,
+    /// Inserts one indent at each cursor, or indents every selected line when a selection spans more than one line
     InsertTab,
+    /// Indents every line a selection touches by one indent unit
+    IndentLines,
+    /// Removes one indent unit from every line a selection touches
+    DedentLines,
+    /// Inserts a copy of every line a selection touches below it
+    DuplicateLines,
+    /// Removes every line a selection touches
+    DeleteLines,
+    /// Swaps the lines a selection touches with the line above them
+    MoveLinesUp,
+    /// Swaps the lines a selection touches with the line below them
+    MoveLinesDown,
+    /// Joins the lines a selection touches, or the cursor's line with the next, separated by a single space
+    JoinLines,
+    /// Inserts an indented line above each selection, and moves the cursor onto it
+    OpenLineAbove,
+    /// Inserts an indented line below each selection, and moves the cursor onto it
+    OpenLineBelow,
+    /// Adds or removes the document language's comment token on every line a selection touches
+    ToggleComment,
+    /// Wraps each selection in open and close
+    Surround{open: char, close: char},
+    /// Removes the nearest pair enclosing each selection. char may be either half of a bracket pair, or a quote
+    DeleteSurround{char: char},
+    /// Replaces the nearest pair of from enclosing each selection with the pair of to
+    ChangeSurround{from: char, to: char},
+    /// Moves each cursor on a bracket to its match, or with extend set, moves only the selection head
+    MatchBracket{#[serde(default)] extend: bool},
     Save,
+    SetEncoding{encoding: Encoding, bom: bool},
+    /// Changes what InsertTab inserts for this document, and how wide tab characters are displayed
+    SetIndent{style: IndentStyle, width: usize},
+    /// Turns auto pairing of brackets and quotes on or off for this document
+    SetAutoPairs{enabled: bool},
+    RecoverSwap,
+    DiscardSwap,
+    /// Stops the server. refused while any document has unsaved changes, unless save is set and every save succeeds, or force 
+    /// is set, in which case unsaved changes are kept in swap files for recovery
+    Shutdown{#[serde(default)] force: bool, #[serde(default)] save: bool},
+}
+/// Splits the bytes received from a client into requests, at each MESSAGE_DELIMITER. bytes after the last delimiter are
+/// kept until the rest of their request arrives
+#[derive(Default)]
+pub struct MessageReader{
+    buffer: Vec<u8>,
+    /// how much of an oversized request has been thrown away so far, while waiting for its delimiter
+    discarded: Option<usize>,
+}
+impl MessageReader{
+    pub fn extend(&mut self, bytes: &[u8]){
+        self.buffer.extend_from_slice(bytes);
+        // an oversized request is never parsed, so it need not be kept while the rest of it arrives
+        if !self.buffer.contains(&MESSAGE_DELIMITER) && self.buffer.len() > MESSAGE_SIZE{
+            *self.discarded.get_or_insert(0) += self.buffer.len();
+            self.buffer.clear();
+        }
+    }
+
+    /// The next complete request, parsed. None until a delimiter arrives. empty lines are skipped
+    pub fn next_message(&mut self) -> Option<Result<ServerAction, EditorError>>{
+        loop{
+            let end = self.buffer.iter().position(|byte| *byte == MESSAGE_DELIMITER)?;
+            let message: Vec<u8> = self.buffer.drain(..=end).take(end).collect();
+            if let Some(discarded) = self.discarded.take(){
+                return Some(Err(EditorError::RequestTooLarge{size: discarded + message.len(), limit: MESSAGE_SIZE}));
+            }
+            if message.iter().all(|byte| byte.is_ascii_whitespace()){continue;}
+
+            return Some(ServerAction::from_message(&message));
+        }
+    }
+
+    /// Whether a complete request is waiting to be parsed
+    pub fn has_message(&self) -> bool{
+        self.buffer.contains(&MESSAGE_DELIMITER)
+    }
+
+    /// How many unhandled bytes are held
+    pub fn buffered(&self) -> usize{
+        self.buffer.len()
+    }
+}
+
+impl ServerAction{
+    /// Parses a request received from a client
+    pub fn from_message(message: &[u8]) -> Result<Self, EditorError>{
+        if message.len() > MESSAGE_SIZE{
+            return Err(EditorError::RequestTooLarge{size: message.len(), limit: MESSAGE_SIZE});
+        }
+        let message = std::str::from_utf8(message).map_err(|e| EditorError::MalformedRequest{message: e.to_string()})?;
+        ron::from_str(message).map_err(|e| match e.code{
+            ron::Error::NoSuchEnumVariant{found, outer: Some(outer), ..} if outer == "ServerAction" => {
+                EditorError::UnknownAction{name: found}
+            }
+            _ => EditorError::MalformedRequest{message: e.to_string()},
+        })
+    }
+
+    /// Whether this action modifies document text, or writes it to disk. these are refused for read only documents
+    pub fn is_edit(&self) -> bool{
+        matches!(
+            self, 
+            ServerAction::Backspace
+            | ServerAction::Delete
+            | ServerAction::InserChar(_)
+            | ServerAction::InsertNewline
+            | ServerAction::InsertTab
+            | ServerAction::IndentLines
+            | ServerAction::DedentLines
+            | ServerAction::DuplicateLines
+            | ServerAction::DeleteLines
+            | ServerAction::MoveLinesUp
+            | ServerAction::MoveLinesDown
+            | ServerAction::JoinLines
+            | ServerAction::OpenLineAbove
+            | ServerAction::OpenLineBelow
+            | ServerAction::ToggleComment
+            | ServerAction::Surround{..}
+            | ServerAction::DeleteSurround{..}
+            | ServerAction::ChangeSurround{..}
+            | ServerAction::Save
+            | ServerAction::SetEncoding{..}
+            | ServerAction::RecoverSwap
+        )
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ServerResponse{
-    FileOpened{file_name: Option<String>, document_length: usize},
-    ConnectionSucceeded,
+    /// view is only sent when OpenFile gave a view size
+    FileOpened{file_name: Option<String>, document_length: usize, encoding: Encoding, recovery_available: bool, read_only: bool, document_cursor_position: Position, large_file: bool, indent: Indent, #[serde(default)] view: Option<ClientView>},
+    /// Sent, unprompted, as soon as a client connects. presenting the token in Resume after reconnecting restores the session
+    ConnectionSucceeded{session_token: String},
     Acknowledge,
-    DisplayView{content: String, line_numbers: String, client_cursor_positions: Vec<Position>, document_cursor_position: Position, modified: bool},
-    Failed(String), //(reason for failure)
-    CursorPosition{client_cursor_positions: Vec<Position>, document_cursor_position: Position}
+    /// matching_brackets are the view positions of the bracket under the primary cursor and its match, for highlighting
+    DisplayView{
+        content: String, 
+        line_numbers: String, 
+        client_cursor_positions: Vec<Position>, 
+        document_cursor_position: Position, 
+        modified: bool, 
+        #[serde(default)] matching_brackets: Vec<Position>
+    },
+    Failed(EditorError), //(reason for failure)
+    /// Sent to every connected client, unprompted, just before the server exits
+    ServerShuttingDown,
+    CursorPosition{client_cursor_positions: Vec<Position>, document_cursor_position: Position, #[serde(default)] matching_brackets: Vec<Position>}
+}
+
+/// The visible part of a document, as DisplayView sends it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClientView{
+    pub content: String,
+    pub line_numbers: String,
+    pub client_cursor_positions: Vec<Position>,
+    pub matching_brackets: Vec<Position>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -91,4 +271,47 @@ impl PartialEq for Position{
         self.x == other.x && self.y == other.y
     }
 }
-impl Eq for Position{}
\ No newline at end of file
+impl Eq for Position{}
+
+
+
+
+
+#[test]
+fn message_reader_splits_requests_at_delimiter(){
+    let mut reader = MessageReader::default();
+    reader.extend(b"MoveCursorRight\nMoveCursorRight\nGoTo(line_");
+    assert!(reader.next_message() == Some(Ok(ServerAction::MoveCursorRight)));
+    assert!(reader.next_message() == Some(Ok(ServerAction::MoveCursorRight)));
+    assert!(reader.next_message().is_none());
+    reader.extend(b"number: 3)\n");
+    assert!(reader.next_message() == Some(Ok(ServerAction::GoTo{line_number: 3, column: None})));
+    assert!(reader.buffered() == 0);
+}
+#[test]
+fn message_reader_discards_oversized_request(){
+    let mut reader = MessageReader::default();
+    reader.extend(&vec![b' '; MESSAGE_SIZE + 1]);
+    assert!(reader.buffered() == 0);
+    reader.extend(b"  \nMoveCursorRight\n");
+    assert!(reader.next_message() == Some(Err(EditorError::RequestTooLarge{size: MESSAGE_SIZE + 3, limit: MESSAGE_SIZE})));
+    assert!(reader.next_message() == Some(Ok(ServerAction::MoveCursorRight)));
+}
+#[test]
+fn from_message_parses_action(){
+    assert!(ServerAction::from_message(b"GoTo(line_number: 3)") == Ok(ServerAction::GoTo{line_number: 3, column: None}));
+}
+#[test]
+fn from_message_reports_unknown_action(){
+    assert!(ServerAction::from_message(b"Idk") == Err(EditorError::UnknownAction{name: "Idk".to_string()}));
+}
+#[test]
+fn from_message_reports_malformed_request(){
+    assert!(matches!(ServerAction::from_message(b"GoTo(line_number: "), Err(EditorError::MalformedRequest{..})));
+    assert!(matches!(ServerAction::from_message(&[0xFF, 0xFE]), Err(EditorError::MalformedRequest{..})));
+}
+#[test]
+fn from_message_rejects_oversized_request(){
+    let message = vec![b' '; MESSAGE_SIZE + 1];
+    assert!(ServerAction::from_message(&message) == Err(EditorError::RequestTooLarge{size: MESSAGE_SIZE + 1, limit: MESSAGE_SIZE}));
+}
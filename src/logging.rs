@@ -0,0 +1,241 @@
+use crate::{ServerAction, ServerResponse};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable holding a log filter, used when no --log-level flag is given
+pub const LOG_LEVEL_VARIABLE: &str = "NLO_LOG";
+/// Environment variable holding a log file path, used when no --log-file flag is given
+pub const LOG_FILE_VARIABLE: &str = "NLO_LOG_FILE";
+/// Log files are created readable by their owner only. they name files being edited, and can hold document text
+pub const LOG_FILE_MODE: u32 = 0o600;
+
+// document text is kept out of the log unless this is set
+static LOG_CONTENT: AtomicBool = AtomicBool::new(false);
+
+
+
+#[derive(Debug, Default)]
+pub struct LogConfig{
+    /// a default level, and/or comma separated module=level overrides. for example "info,nlo_text_editor_server::editor=debug"
+    pub filter: Option<String>,
+    /// log to this file instead of stderr
+    pub file: Option<PathBuf>,
+    /// include document text, and typed characters, in logged requests and responses
+    pub content: bool,
+}
+
+/// Log levels for the whole server, with overrides for individual modules
+#[derive(Debug, PartialEq)]
+struct Filter{
+    default_level: LevelFilter,
+    /// the level for every module whose path starts with the prefix. the longest matching prefix wins
+    module_levels: Vec<(String, LevelFilter)>,
+}
+impl Filter{
+    fn parse(spec: &str) -> Result<Self, Box<dyn Error>>{
+        let mut filter = Filter{default_level: LevelFilter::Info, module_levels: Vec::new()};
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()){
+            match directive.split_once('='){
+                Some((module, level)) => filter.module_levels.push((module.trim().to_string(), parse_level(level)?)),
+                None => filter.default_level = parse_level(directive)?,
+            }
+        }
+        filter.module_levels.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+
+        Ok(filter)
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter{
+        for (module, level) in &self.module_levels{
+            if target == module || target.starts_with(&format!("{}::", module)){
+                return *level;
+            }
+        }
+
+        self.default_level
+    }
+
+    fn max_level(&self) -> LevelFilter{
+        self.module_levels.iter().map(|(_, level)| *level).fold(self.default_level, Ord::max)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, Box<dyn Error>>{
+    level.trim().parse().map_err(|_| format!("unknown log level: {}", level.trim()).into())
+}
+
+struct Logger{
+    filter: Filter,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+impl Log for Logger{
+    fn enabled(&self, metadata: &Metadata) -> bool{
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record){
+        if !self.enabled(record.metadata()){return;}
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut output = self.output.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // nowhere left to report a failure to write a log line
+        let _ = writeln!(
+            output,
+            "{}.{:03} {:<5} {}: {}",
+            timestamp.as_secs(),
+            timestamp.subsec_millis(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self){
+        let _ = self.output.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).flush();
+    }
+}
+
+/// Installs the server's logger. settings missing from config are read from the environment
+pub fn init(config: LogConfig) -> Result<(), Box<dyn Error>>{
+    let filter = match config.filter.or_else(|| std::env::var(LOG_LEVEL_VARIABLE).ok()){
+        Some(spec) => Filter::parse(&spec)?,
+        None => Filter::parse("info")?,
+    };
+    let output: Box<dyn Write + Send> = match config.file.or_else(|| std::env::var_os(LOG_FILE_VARIABLE).map(PathBuf::from)){
+        Some(path) => Box::new(OpenOptions::new().create(true).append(true).mode(LOG_FILE_MODE).open(path)?),
+        None => Box::new(io::stderr()),
+    };
+    LOG_CONTENT.store(config.content, Ordering::Relaxed);
+
+    log::set_max_level(filter.max_level());
+    log::set_boxed_logger(Box::new(Logger{filter, output: Mutex::new(output)}))?;
+
+    Ok(())
+}
+
+/// A loggable description of a request, with typed text removed unless content logging is enabled. session tokens are 
+/// always removed, because a token is all it takes to resume someone else's session
+pub fn describe_action(action: &ServerAction) -> String{
+    if LOG_CONTENT.load(Ordering::Relaxed) && !matches!(action, ServerAction::Resume{..}){
+        return format!("{:?}", action);
+    }
+
+    redact_action(action)
+}
+fn redact_action(action: &ServerAction) -> String{
+    match action{
+        ServerAction::InserChar(_) => "InserChar(<redacted>)".to_string(),
+        ServerAction::Resume{..} => "Resume { session_token: <redacted> }".to_string(),
+        _ => format!("{:?}", action),
+    }
+}
+
+/// A loggable description of a response, with document text removed unless content logging is enabled
+pub fn describe_response(response: &ServerResponse) -> String{
+    if LOG_CONTENT.load(Ordering::Relaxed){
+        return format!("{:?}", response);
+    }
+
+    redact_response(response)
+}
+fn redact_response(response: &ServerResponse) -> String{
+    match response{
+        ServerResponse::DisplayView{content, line_numbers: _, client_cursor_positions, document_cursor_position, modified, matching_brackets} => {
+            format!(
+                "DisplayView {{ content: <{} bytes redacted>, client_cursor_positions: {:?}, document_cursor_position: {:?}, modified: {}, matching_brackets: {:?} }}",
+                content.len(),
+                client_cursor_positions,
+                document_cursor_position,
+                modified,
+                matching_brackets
+            )
+        }
+        ServerResponse::FileOpened{file_name, document_length, encoding, recovery_available, read_only, document_cursor_position, large_file, indent, view: Some(view)} => {
+            format!(
+                "FileOpened {{ file_name: {:?}, document_length: {}, encoding: {:?}, recovery_available: {}, read_only: {}, document_cursor_position: {:?}, large_file: {}, indent: {:?}, view: <{} bytes redacted> }}",
+                file_name,
+                document_length,
+                encoding,
+                recovery_available,
+                read_only,
+                document_cursor_position,
+                large_file,
+                indent,
+                view.content.len()
+            )
+        }
+        _ => format!("{:?}", response),
+    }
+}
+
+
+
+
+
+#[test]
+fn filter_parses_default_and_module_levels(){
+    let filter = Filter::parse("warn, nlo_text_editor_server::editor=debug").unwrap();
+    assert!(filter.default_level == LevelFilter::Warn);
+    assert!(filter.module_levels == vec![("nlo_text_editor_server::editor".to_string(), LevelFilter::Debug)]);
+    assert!(filter.max_level() == LevelFilter::Debug);
+}
+#[test]
+fn filter_rejects_unknown_level(){
+    assert!(Filter::parse("loud").is_err());
+}
+#[test]
+fn filter_uses_longest_matching_module(){
+    let filter = Filter::parse("error,idk=info,idk::something=trace").unwrap();
+    assert!(filter.level_for("idk::something::else") == LevelFilter::Trace);
+    assert!(filter.level_for("idk::other") == LevelFilter::Info);
+    assert!(filter.level_for("idk") == LevelFilter::Info);
+    assert!(filter.level_for("idkwhat") == LevelFilter::Error);
+}
+#[test]
+fn redact_action_hides_typed_characters(){
+    assert!(!redact_action(&ServerAction::InserChar('x')).contains('x'));
+}
+#[test]
+fn redact_action_hides_session_token(){
+    let token = crate::editor::new_session_token();
+    assert!(!describe_action(&ServerAction::Resume{session_token: token.clone()}).contains(&token));
+}
+#[test]
+fn redact_response_hides_document_text(){
+    let response = ServerResponse::DisplayView{
+        content: "secret\n".to_string(),
+        line_numbers: "1\n".to_string(),
+        client_cursor_positions: Vec::new(),
+        document_cursor_position: crate::Position::default(),
+        modified: false,
+        matching_brackets: Vec::new()
+    };
+    assert!(!redact_response(&response).contains("secret"));
+}
+#[test]
+fn redact_response_hides_opened_view(){
+    let response = ServerResponse::FileOpened{
+        file_name: Some("idk.txt".to_string()),
+        document_length: 1,
+        encoding: crate::encoding::Encoding::default(),
+        recovery_available: false,
+        read_only: false,
+        document_cursor_position: crate::Position::default(),
+        large_file: false,
+        indent: crate::indent::Indent::default(),
+        view: Some(crate::ClientView{
+            content: "secret\n".to_string(),
+            line_numbers: "1\n".to_string(),
+            client_cursor_positions: Vec::new(),
+            matching_brackets: Vec::new()
+        })
+    };
+    assert!(!redact_response(&response).contains("secret"));
+}
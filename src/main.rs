@@ -1,81 +1,538 @@
 /// This will be the main loop for the editor process/daemon(is this really a daemon, by definition?)
-use nlo_text_editor_server::{editor::Editor, ServerAction};
-use nlo_text_editor_server::ServerResponse;
-use nlo_text_editor_server::MESSAGE_SIZE;
-use std::sync::{Arc, Mutex};
-use std::{io::Write, net::{TcpListener, TcpStream}};
-use std::io::Read;
+use nlo_text_editor_server::{editor::{self, Editor, ORPHAN_GRACE_PERIOD}, error::EditorError, ServerAction};
+use nlo_text_editor_server::{ClientView, ServerResponse};
+use nlo_text_editor_server::{MessageReader, IDLE_TIMEOUT, MESSAGE_DELIMITER, MESSAGE_SIZE, READ_BUFFER_LIMIT, SERVER_ADDRESS};
+use nlo_text_editor_server::daemon;
+use nlo_text_editor_server::logging::{self, LogConfig};
+use log::{debug, error, info, warn};
+use std::path::PathBuf;
+use nlo_text_editor_server::swap::{self, AUTOSAVE_INTERVAL};
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook_mio::v1_0::Signals;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, mpsc::{self, Receiver, Sender}};
+use std::io::{self, Read, Write};
 use std::error::Error;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+use std::os::unix::io::AsRawFd;
 
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+const SIGNALS: Token = Token(2);
+const FIRST_CLIENT_TOKEN: usize = 3;
+/// Requests are handled on a fixed number of worker threads, no matter how many clients are connected
+const WORKER_COUNT: usize = 4;
+/// How long to keep trying to tell clients about a shutdown, before exiting regardless
+const SHUTDOWN_NOTICE_TIMEOUT: Duration = Duration::from_secs(1);
+/// The longest the event loop waits for events, before checking for idle connections regardless
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// A connection quiet for this long is probed by tcp keepalive. a client that stops answering the probes, because its 
+/// machine or network went away, is disconnected after KEEPALIVE_PROBES more intervals
+#[cfg(target_os = "linux")]
+const KEEPALIVE_IDLE: Duration = Duration::from_secs(60);
+#[cfg(target_os = "linux")]
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+#[cfg(target_os = "linux")]
+const KEEPALIVE_PROBES: u32 = 6;
 
-fn main(){
-    let counter = Arc::new(Mutex::new(Editor::default()));
-    let mut handles = Vec::new();
-    
-    // set up client/server stuff
-    let listener = TcpListener::bind("127.0.0.1:7878").expect("failed to bind to port");
-    println!("Server listening on port 7878\n");
-    
-    for stream in listener.incoming(){
-        match stream{
-            Ok(stream) => {
-                let counter = Arc::clone(&counter);
-                let handle = std::thread::spawn(move ||{
-                    let _ = handle_client(stream, counter);
-                });
-                handles.push(handle);
-            }
-            Err(e) => {
-                println!("Failed to establish connection: {}", e);
+
+
+/// A request read from a client, waiting to be handled by a worker
+struct Job{
+    token: Token,
+    client_address: String,
+    session_token: String,
+    action: ServerAction,
+}
+
+/// Anything a worker, or any other thread, wants written to a client. sent to the event loop, which owns the sockets
+struct Outgoing{
+    token: Token,
+    response: Option<ServerResponse>,
+    /// this answers the client's in flight request, so its next request can be handled
+    finishes_request: bool,
+    /// close the connection once the response has been written
+    close: bool,
+    /// the server should exit once the response has been queued
+    shutdown: bool,
+    /// the connection now belongs to this session
+    session_token: Option<String>,
+}
+
+struct Connection{
+    stream: TcpStream,
+    client_address: String,
+    /// the session whose document this connection works on
+    session_token: String,
+    read_buffer: MessageReader,
+    write_buffer: Vec<u8>,
+    /// reading stopped with READ_BUFFER_LIMIT bytes unhandled. it resumes as requests are handled
+    read_paused: bool,
+    /// a worker is handling a request from this client. later requests wait, so responses are sent in order
+    busy: bool,
+    /// stop reading, and close once everything queued has been written
+    closing: bool,
+    /// the socket is gone, or the client closed it. nothing else is read or written, but requests already received are
+    /// still handled, before the connection is removed
+    disconnected: bool,
+    /// when the client last sent anything
+    last_activity: Instant,
+}
+impl Connection{
+    fn new(stream: TcpStream, client_address: String) -> Self{
+        Self{stream, client_address, session_token: editor::new_session_token(), read_buffer: MessageReader::default(), write_buffer: Vec::new(), read_paused: false, busy: false, closing: false, disconnected: false, last_activity: Instant::now()}
+    }
+
+    // the socket is edge triggered, so everything available must be read now
+    fn receive(&mut self){
+        let mut buffer = [0u8; MESSAGE_SIZE];
+        loop{
+            if self.read_buffer.buffered() >= READ_BUFFER_LIMIT{
+                self.read_paused = true;
+                return;
+            }
+            match self.stream.read(&mut buffer){
+                Ok(0) => {
+                    self.disconnected = true;
+                    return;
+                }
+                Ok(size) => {
+                    self.read_buffer.extend(&buffer[..size]);
+                    self.last_activity = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    warn!("An error occurred. Terminating connection with {}. error: {}", self.client_address, e);
+                    self.disconnected = true;
+                    return;
+                }
             }
         }
     }
+
+    fn queue(&mut self, response: &ServerResponse){
+        match ron::to_string(response){
+            Ok(serialized_response) => {
+                self.write_buffer.extend_from_slice(serialized_response.as_bytes());
+                self.write_buffer.push(MESSAGE_DELIMITER);
+            }
+            Err(e) => error!("{}: failed to serialize response. error: {}", self.client_address, e),
+        }
+    }
+
+    // writes as much of the write buffer as the socket will currently accept. the rest is written on the next writable event
+    fn flush(&mut self){
+        if self.disconnected{
+            self.write_buffer.clear();
+            return;
+        }
+        while !self.write_buffer.is_empty(){
+            match self.stream.write(&self.write_buffer){
+                Ok(0) => {
+                    self.disconnected = true;
+                    return;
+                }
+                Ok(bytes_written) => {self.write_buffer.drain(..bytes_written);}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    warn!("An error occurred. Terminating connection with {}. error: {}", self.client_address, e);
+                    self.disconnected = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Whether the client has gone quiet for longer than IDLE_TIMEOUT. a client waiting on its own request is not idle
+    fn is_idle(&self) -> bool{
+        !self.busy && self.last_activity.elapsed() >= IDLE_TIMEOUT
+    }
+
+    fn is_finished(&self) -> bool{
+        if self.busy{
+            return false;
+        }
+        if self.disconnected{
+            // a closing connection handles nothing more
+            return self.closing || !self.read_buffer.has_message();
+        }
+        self.closing && self.write_buffer.is_empty()
+    }
 }
-    
-fn handle_client(mut stream: TcpStream, counter: Arc<Mutex<Editor>>) -> Result<(), Box<dyn Error>>{
-    let client_address = stream.peer_addr().unwrap().to_string();
-    
-    // loop and get requests
-    let mut read_buffer = [0u8; MESSAGE_SIZE];
+
+
+fn main(){
+    let mut run_as_daemon = false;
+    let mut log_config = LogConfig::default();
+    let mut arguments = std::env::args().skip(1);
+    while let Some(argument) = arguments.next(){
+        match argument.as_str(){
+            "--daemon" => run_as_daemon = true,
+            "--log-level" => log_config.filter = Some(argument_value(&argument, arguments.next())),
+            "--log-file" => log_config.file = Some(PathBuf::from(argument_value(&argument, arguments.next()))),
+            "--log-content" => log_config.content = true,
+            _ => exit_with_usage(&format!("unknown argument: {}", argument)),
+        }
+    }
+    if let Err(e) = logging::init(log_config){
+        exit_with_usage(&format!("failed to set up logging. error: {}", e));
+    }
+
+    if let Some(pid) = daemon::running_instance(){
+        eprintln!("Server already running with pid {}", pid);
+        std::process::exit(1);
+    }
+    // bind before detaching, so a failure is reported to whoever started the server
+    let listener = match std::net::TcpListener::bind(SERVER_ADDRESS){
+        Ok(listener) => listener,
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            eprintln!("{} is already in use. is another server running?", SERVER_ADDRESS);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to bind to {}. error: {}", SERVER_ADDRESS, e);
+            std::process::exit(1);
+        }
+    };
+    if run_as_daemon{
+        if let Err(e) = daemon::daemonize(&daemon::log_file_path()){
+            eprintln!("Failed to daemonize. error: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Err(e) = daemon::write_pid_file(){
+        warn!("Failed to write pid file. error: {}", e);
+    }
+
+    let editor = Arc::new(Editor::new(swap::state_dir()));
+
+    // periodically persist unsaved edits, so they can be recovered if the server dies
+    let autosave_editor = Arc::clone(&editor);
+    std::thread::spawn(move ||{
+        loop{
+            std::thread::sleep(AUTOSAVE_INTERVAL);
+            autosave_editor.write_swap_files();
+            autosave_editor.drop_expired_orphans(ORPHAN_GRACE_PERIOD);
+        }
+    });
+
+    match serve(listener, editor){
+        Ok(_) => info!("Server shut down"),
+        Err(e) => error!("Server stopped. error: {}", e),
+    }
+    daemon::remove_pid_file();
+}
+
+fn argument_value(argument: &str, value: Option<String>) -> String{
+    match value{
+        Some(value) => value,
+        None => exit_with_usage(&format!("{} needs a value", argument)),
+    }
+}
+
+fn exit_with_usage(reason: &str) -> !{
+    eprintln!(
+        "{}\nusage: {} [--daemon] [--log-level <level>[,<module>=<level>...]] [--log-file <path>] [--log-content]\n\
+        the {} and {} environment variables are used when --log-level or --log-file are not given", 
+        reason, daemon::SERVER_PROGRAM, logging::LOG_LEVEL_VARIABLE, logging::LOG_FILE_VARIABLE
+    );
+    std::process::exit(2);
+}
+
+/// Runs the event loop. one thread waits on every client socket at once, and hands complete requests to a fixed pool of 
+/// workers, so idle clients cost no more than their socket
+fn serve(listener: std::net::TcpListener, editor: Arc<Editor>) -> Result<(), Box<dyn Error>>{
+    let mut poll = Poll::new()?;
+    listener.set_nonblocking(true)?;
+    let mut listener = TcpListener::from_std(listener);
+    poll.registry().register(&mut listener, LISTENER, Interest::READABLE)?;
+    let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    poll.registry().register(&mut signals, SIGNALS, Interest::READABLE)?;
+    info!("Server listening on {}", SERVER_ADDRESS);
+
+    let (job_sender, job_receiver) = mpsc::channel::<Job>();
+    let job_receiver = Arc::new(Mutex::new(job_receiver));
+    let (outgoing_sender, outgoing_receiver) = mpsc::channel::<Outgoing>();
+    for _ in 0..WORKER_COUNT{
+        let job_receiver = Arc::clone(&job_receiver);
+        let outgoing_sender = outgoing_sender.clone();
+        let waker = Arc::clone(&waker);
+        let editor = Arc::clone(&editor);
+        std::thread::spawn(move ||{
+            work(job_receiver, outgoing_sender, waker, editor);
+        });
+    }
+
+    let mut connections: HashMap<Token, Connection> = HashMap::new();
+    let mut next_token = FIRST_CLIENT_TOKEN;
+    let mut events = Events::with_capacity(1024);
+    let mut shutting_down = false;
     loop{
-        match stream.read(&mut read_buffer){
-            Ok(size) => {
-                // deserialize requests to actions, if possible
-                let my_string = String::from_utf8_lossy(&read_buffer[0..size]);
-                let action: ServerAction = match ron::from_str(&my_string){
-                    Ok(action) => {action}
-                    Err(e) => {return Err(Box::new(e));}
-                };
-                println!("server received: {:#?}", action);
-                
-                // perform requested action, if valid, and generate response
-                let mut editor = counter.lock().unwrap();
-                if let Some(response) = server_action_to_response(action, &client_address, &mut editor){
-                    let serialized_response = ron::to_string(&response)?;
-                    match stream.write(serialized_response.as_bytes()){
-                        Ok(_bytes_written) => {
-                            //if bytes_written == 0{}else{}
+        if let Err(e) = poll.poll(&mut events, Some(IDLE_CHECK_INTERVAL)){
+            if e.kind() == io::ErrorKind::Interrupted{continue;}
+            return Err(Box::new(e));
+        }
+
+        for event in events.iter(){
+            match event.token(){
+                LISTENER => {
+                    loop{
+                        match listener.accept(){
+                            Ok((mut stream, address)) => {
+                                let token = Token(next_token);
+                                next_token += 1;
+                                match poll.registry().register(&mut stream, token, Interest::READABLE | Interest::WRITABLE){
+                                    Ok(_) => {
+                                        if let Err(e) = enable_keepalive(&stream){
+                                            warn!("{}: failed to enable keepalive. error: {}", address, e);
+                                        }
+                                        let mut connection = Connection::new(stream, address.to_string());
+                                        connection.queue(&ServerResponse::ConnectionSucceeded{session_token: connection.session_token.clone()});
+                                        connection.flush();
+                                        connections.insert(token, connection);
+                                    }
+                                    Err(e) => warn!("Failed to establish connection: {}", e),
+                                }
+                            }
+                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                            Err(e) => {
+                                warn!("Failed to establish connection: {}", e);
+                                break;
+                            }
                         }
-                        Err(e) => {return Err(Box::new(e));}
                     }
-                    stream.flush().unwrap();
-                    println!("server emitted: {:#?}\n", response);
+                }
+                // workers wake the loop after queueing a response. those are all handled below
+                WAKER => {}
+                // nobody can be asked about unsaved changes here, so they are kept in swap files for recovery
+                SIGNALS => {
+                    for signal in signals.pending(){
+                        info!("received signal {}. shutting down", signal);
+                        shutting_down = true;
+                    }
+                    if shutting_down{
+                        let _ = editor.prepare_shutdown(true, false);
+                    }
+                }
+                token => {
+                    if let Some(connection) = connections.get_mut(&token){
+                        // a failed keepalive is reported as an error, which the next read returns
+                        if (event.is_readable() || event.is_error()) && !connection.closing && !connection.disconnected{
+                            connection.receive();
+                        }
+                        if event.is_writable(){
+                            connection.flush();
+                        }
+                    }
+                }
+            }
+        }
+
+        while let Ok(outgoing) = outgoing_receiver.try_recv(){
+            // the client may have disconnected while its request was being handled
+            if let Some(connection) = connections.get_mut(&outgoing.token){
+                if let Some(response) = &outgoing.response{
+                    connection.queue(response);
+                    debug!("{}: emitted {}", connection.client_address, logging::describe_response(response));
+                }
+                if outgoing.finishes_request{
+                    connection.busy = false;
+                }
+                if outgoing.close{
+                    connection.closing = true;
+                }
+                if let Some(session_token) = outgoing.session_token{
+                    info!("{}: resumed session", connection.client_address);
+                    connection.session_token = session_token;
+                }
+                connection.flush();
+            }
+            if outgoing.shutdown{
+                shutting_down = true;
+            }
+        }
+
+        if shutting_down{
+            notify_shutdown(&mut connections);
+            return Ok(());
+        }
+
+        // one request per client is in flight at a time. requests a client sent before it disconnected are still handled,
+        // so a CloseConnection followed by closing the socket closes the document
+        for (token, connection) in connections.iter_mut(){
+            while !connection.busy && !connection.closing{
+                if connection.read_paused && !connection.disconnected{
+                    connection.read_paused = false;
+                    connection.receive();
+                }
+                // deserialize requests to actions, if possible
+                let Some(message) = connection.read_buffer.next_message() else{break;};
+                match message{
+                    Ok(action) => {
+                        debug!("{}: received {}", connection.client_address, logging::describe_action(&action));
+                        connection.busy = true;
+                        job_sender.send(Job{
+                            token: *token, 
+                            client_address: connection.client_address.clone(), 
+                            session_token: connection.session_token.clone(), 
+                            action
+                        })?;
+                    }
+                    // a newer client may send actions this server does not know. it is told so, and can carry on
+                    Err(e) => {
+                        warn!("{}: failed to parse request. error: {}", connection.client_address, e);
+                        let response = ServerResponse::Failed(e);
+                        connection.queue(&response);
+                        debug!("{}: emitted {}", connection.client_address, logging::describe_response(&response));
+                        connection.flush();
+                    }
                 }
             }
-            Err(e) => {
-                println!("An error occurred. Terminating connection with {}. error: {}", client_address, e);
-                break;
+        }
+
+        for connection in connections.values_mut(){
+            if !connection.disconnected && !connection.closing && connection.is_idle(){
+                info!("{}: idle for {:?}. closing connection", connection.client_address, IDLE_TIMEOUT);
+                connection.disconnected = true;
             }
         }
+
+        // dropping a connection closes its socket, and deregisters it from the poll.
+        // a client that vanished without closing its document may come back for it, so the document is kept for a while.
+        // unless it already has, on a new connection that resumed the session before this one was noticed to be dead
+        let attached_sessions: HashSet<String> = connections.values()
+            .filter(|connection| !connection.disconnected)
+            .map(|connection| connection.session_token.clone())
+            .collect();
+        connections.retain(|_, connection|{
+            if !connection.is_finished(){
+                return true;
+            }
+            if connection.disconnected && !attached_sessions.contains(&connection.session_token){
+                editor.orphan_document(&connection.session_token);
+            }
+            false
+        });
+    }
+}
+
+// so a client that vanished without closing its socket is noticed, without it having to send anything
+fn enable_keepalive(stream: &TcpStream) -> io::Result<()>{
+    let set_option = |level: libc::c_int, name: libc::c_int, value: libc::c_int| {
+        let result = unsafe{
+            libc::setsockopt(
+                stream.as_raw_fd(), 
+                level, 
+                name, 
+                &value as *const libc::c_int as *const libc::c_void, 
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t
+            )
+        };
+        if result == -1{Err(io::Error::last_os_error())}else{Ok(())}
+    };
+    set_option(libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+    #[cfg(target_os = "linux")]
+    {
+        set_option(libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, KEEPALIVE_IDLE.as_secs() as libc::c_int)?;
+        set_option(libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, KEEPALIVE_INTERVAL.as_secs() as libc::c_int)?;
+        set_option(libc::IPPROTO_TCP, libc::TCP_KEEPCNT, KEEPALIVE_PROBES as libc::c_int)?;
     }
 
     Ok(())
 }
 
-fn server_action_to_response(action: ServerAction, client_address: &str, editor: &mut Editor) -> Option<ServerResponse>{
+// best effort. a client that is not reading can not hold up the shutdown
+fn notify_shutdown(connections: &mut HashMap<Token, Connection>){
+    for connection in connections.values_mut(){
+        connection.queue(&ServerResponse::ServerShuttingDown);
+    }
+    let deadline = Instant::now() + SHUTDOWN_NOTICE_TIMEOUT;
+    while Instant::now() < deadline{
+        for connection in connections.values_mut(){
+            connection.flush();
+        }
+        if connections.values().all(|connection| connection.disconnected || connection.write_buffer.is_empty()){
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn work(jobs: Arc<Mutex<Receiver<Job>>>, outgoing: Sender<Outgoing>, waker: Arc<Waker>, editor: Arc<Editor>){
+    loop{
+        // the lock is released as soon as a job is received, so other workers can wait for the next one
+        let job = match editor::lock(&jobs).recv(){
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        // perform requested action, if valid, and generate response.
+        // a panic is contained to this client, so the shared editor state stays usable by everyone else
+        let shutdown_requested = matches!(job.action, ServerAction::Shutdown{..});
+        let resumed_session = match &job.action{
+            ServerAction::Resume{session_token} => Some(session_token.clone()),
+            _ => None,
+        };
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| server_action_to_response(job.action, &job.session_token, &editor))){
+            Ok(response) => {
+                // a refused shutdown, or resume, is answered with Failed
+                let shutdown = shutdown_requested && matches!(response, Some(ServerResponse::Acknowledge));
+                let session_token = resumed_session.filter(|_| matches!(response, Some(ServerResponse::FileOpened{..})));
+                Outgoing{token: job.token, response, finishes_request: true, close: false, shutdown, session_token}
+            }
+            Err(payload) => {
+                error!("{}: panicked while handling request. closing connection. error: {}", job.client_address, panic_message(payload.as_ref()));
+                // the document may have been left half edited. its last swap file is kept for recovery
+                editor.discard_document(&job.session_token);
+                Outgoing{
+                    token: job.token, 
+                    response: Some(ServerResponse::Failed(EditorError::Internal{message: panic_message(payload.as_ref()).to_string()})), 
+                    finishes_request: true, 
+                    close: true,
+                    shutdown: false,
+                    session_token: None
+                }
+            }
+        };
+
+        if outgoing.send(result).is_err(){return;}
+        let _ = waker.wake();
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str{
+    if let Some(message) = payload.downcast_ref::<&str>(){
+        message
+    }else if let Some(message) = payload.downcast_ref::<String>(){
+        message
+    }else{
+        "unknown panic"
+    }
+}
+
+/// Only the document registry is locked while looking up the client's document, so clients working in different documents 
+/// never wait on each other
+fn server_action_to_response(action: ServerAction, session_token: &str, editor: &Editor) -> Option<ServerResponse>{
+    if action.is_edit(){
+        if let Some(doc) = editor.document(session_token){
+            let doc = editor::lock(&doc);
+            if doc.is_read_only(){
+                return Some(ServerResponse::Failed(EditorError::ReadOnly{path: doc.file_name().map(PathBuf::from)}));
+            }
+        }
+    }
+
     match action{
         ServerAction::Backspace => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.backspace();
                 let _ = doc.scroll_view_following_cursor();
                 Some(ServerResponse::DisplayView{
@@ -83,22 +540,57 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
         ServerAction::CloseConnection => {
-            if let Some(doc) = editor.document(client_address){
-                println!("{}: closing {}", client_address, doc.file_name().unwrap());
+            if let Some(doc) = editor.document(session_token){
+                info!("{}: closing {}", editor::session_id(session_token), editor::lock(&doc).file_name().unwrap_or_default());
             }
-            editor.close_document(client_address);
+            editor.close_document(session_token);
 
             None
         },
+        ServerAction::Heartbeat => Some(ServerResponse::Acknowledge),
+        ServerAction::SaveSession{name} => {
+            match editor.save_session(&name, session_token){
+                Ok(_) => Some(ServerResponse::Acknowledge),
+                Err(e) => Some(ServerResponse::Failed(e)),
+            }
+        }
+        ServerAction::LoadSession{name} => {
+            match editor.load_session(&name, session_token){
+                Ok(_) => {
+                    if let Some(doc) = editor.document(session_token){
+                        let doc = editor::lock(&doc);
+                        Some(ServerResponse::FileOpened{file_name: doc.file_name(), document_length: doc.len_lines(), encoding: doc.encoding(), recovery_available: doc.recovery_available(), read_only: doc.is_read_only(), document_cursor_position: doc.document_cursor_position(), large_file: doc.is_large_file(), indent: doc.indent(), view: None})
+                    }else{
+                        Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+                    }
+                }
+                Err(e) => Some(ServerResponse::Failed(e)),
+            }
+        }
+        ServerAction::Resume{session_token: resumed_session} => {
+            match editor.resume_session(&resumed_session, session_token){
+                Ok(_) => {
+                    if let Some(doc) = editor.document(&resumed_session){
+                        let doc = editor::lock(&doc);
+                        Some(ServerResponse::FileOpened{file_name: doc.file_name(), document_length: doc.len_lines(), encoding: doc.encoding(), recovery_available: doc.recovery_available(), read_only: doc.is_read_only(), document_cursor_position: doc.document_cursor_position(), large_file: doc.is_large_file(), indent: doc.indent(), view: None})
+                    }else{
+                        Some(ServerResponse::Failed(EditorError::UnknownSession))
+                    }
+                }
+                Err(e) => Some(ServerResponse::Failed(e)),
+            }
+        }
         ServerAction::Delete => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.delete();
                 let _ = doc.scroll_view_following_cursor();
                 Some(ServerResponse::DisplayView{
@@ -106,43 +598,61 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
-        ServerAction::GoTo{line_number} => {
-            if let Some(doc) = editor.document_mut(client_address){
-                doc.go_to(line_number);
+        ServerAction::GoTo{line_number, column} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.go_to(line_number, column);
                 let _ = doc.scroll_view_following_cursor();
                 Some(ServerResponse::DisplayView{
                     content: doc.get_client_view_text(), 
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
-        ServerAction::OpenFile{file_path} => {
-            match editor.open_document(&file_path, client_address){
+        ServerAction::OpenFile{file_path, read_only, line, column, force, view_size} => {
+            let (file_path, parsed_line, parsed_column) = editor::parse_file_location(&file_path);
+            match editor.open_document(&file_path, read_only, force, session_token){
                 Ok(_) => {
-                    if let Some(doc) = editor.document(client_address){
-                        Some(ServerResponse::FileOpened{file_name: doc.file_name(), document_length: /*doc.lines().len()*/doc.text().len_lines()})
+                    if let Some(doc) = editor.document(session_token){
+                        let mut doc = editor::lock(&doc);
+                        if let Some(line_number) = line.or(parsed_line){
+                            doc.go_to(line_number, column.or(parsed_column));
+                        }
+                        // without a view size, the view follows the cursor once the client reports one
+                        let view = view_size.map(|(width, height)| {
+                            doc.set_client_view_size(width as usize, height as usize);
+                            let _ = doc.scroll_view_following_cursor();
+                            ClientView{
+                                content: doc.get_client_view_text(), 
+                                line_numbers: doc.get_client_view_line_numbers(), 
+                                client_cursor_positions: doc.get_client_cursor_positions(), 
+                                matching_brackets: doc.get_client_matching_brackets()
+                            }
+                        });
+                        Some(ServerResponse::FileOpened{file_name: doc.file_name(), document_length: /*doc.lines().len()*/doc.len_lines(), encoding: doc.encoding(), recovery_available: doc.recovery_available(), read_only: doc.is_read_only(), document_cursor_position: doc.document_cursor_position(), large_file: doc.is_large_file(), indent: doc.indent(), view})
                     }else{
-                        Some(ServerResponse::Failed("no document open".to_string()))
+                        Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
                     }
                 }
-                Err(e) => {
-                    Some(ServerResponse::Failed(format!("{}", e)))
-                }
+                Err(e) => Some(ServerResponse::Failed(e)),
             }
         },
         ServerAction::UpdateClientViewSize{width, height} => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.set_client_view_size(width as usize, height as usize);
                 let _ = doc.scroll_view_following_cursor();
                 Some(ServerResponse::DisplayView{
@@ -150,70 +660,80 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::ScrollClientViewDown{amount} => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.scroll_client_view_down(amount);
                 Some(ServerResponse::DisplayView{
                     content: doc.get_client_view_text(), 
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::ScrollClientViewLeft{amount} => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.scroll_client_view_left(amount);
                 Some(ServerResponse::DisplayView{
                     content: doc.get_client_view_text(), 
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::ScrollClientViewRight{amount} => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.scroll_client_view_right(amount);
                 Some(ServerResponse::DisplayView{
                     content: doc.get_client_view_text(), 
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::ScrollClientViewUp{amount} => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.scroll_client_view_up(amount);
                 Some(ServerResponse::DisplayView{
                     content: doc.get_client_view_text(), 
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorDocumentEnd => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_document_end();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -222,20 +742,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
         ServerAction::MoveCursorDocumentStart => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_document_start();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -244,20 +767,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
         ServerAction::MoveCursorDown => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_down();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -266,20 +792,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorUp => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_up();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -288,20 +817,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorRight => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_right();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -310,20 +842,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorLeft => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_left();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -332,20 +867,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorLineEnd => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_end();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -354,20 +892,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorLineStart => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_home();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -376,20 +917,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorPageDown => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_page_down();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -398,20 +942,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::MoveCursorPageUp => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.move_cursors_page_up();
                 let should_update_client_view = doc.scroll_view_following_cursor();
                 if should_update_client_view{
@@ -420,20 +967,23 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                         line_numbers: doc.get_client_view_line_numbers(), 
                         client_cursor_positions: doc.get_client_cursor_positions(), 
                         document_cursor_position: doc.document_cursor_position(), 
-                        modified: doc.is_modified()
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }else{
                     Some(ServerResponse::CursorPosition{
                         client_cursor_positions: doc.get_client_cursor_positions(), 
-                        document_cursor_position: doc.document_cursor_position()
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
                     })
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         },
         ServerAction::InserChar(c) => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.insert_char(c);
                 let _ = doc.scroll_view_following_cursor();
                 Some(ServerResponse::DisplayView{
@@ -441,30 +991,33 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
         ServerAction::InsertNewline => {
-            if let Some(doc) = editor.document_mut(client_address){
-                //doc.enter();
-                doc.insert_char('\n');
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.enter();
                 let _ = doc.scroll_view_following_cursor();
                 Some(ServerResponse::DisplayView{
                     content: doc.get_client_view_text(), 
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
         ServerAction::InsertTab => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 doc.tab();
                 let _ = doc.scroll_view_following_cursor();
                 Some(ServerResponse::DisplayView{
@@ -472,14 +1025,262 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                     line_numbers: doc.get_client_view_line_numbers(), 
                     client_cursor_positions: doc.get_client_cursor_positions(), 
                     document_cursor_position: doc.document_cursor_position(), 
-                    modified: doc.is_modified()
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::IndentLines => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.indent_lines();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
                 })
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
             }
         }
+        ServerAction::DedentLines => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.dedent_lines();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::DuplicateLines => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.duplicate_lines();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::DeleteLines => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.delete_lines();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::MoveLinesUp => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.move_lines_up();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::MoveLinesDown => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.move_lines_down();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::JoinLines => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.join_lines();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::OpenLineAbove => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.open_line_above();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::OpenLineBelow => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.open_line_below();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::ToggleComment => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.toggle_comment();
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::Surround{open, close} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.surround(open, close);
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::DeleteSurround{char} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.delete_surround(char);
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::ChangeSurround{from, to} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.change_surround(from, to);
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::MatchBracket{extend} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.match_bracket(extend);
+                let should_update_client_view = doc.scroll_view_following_cursor();
+                if should_update_client_view{
+                    Some(ServerResponse::DisplayView{
+                        content: doc.get_client_view_text(), 
+                        line_numbers: doc.get_client_view_line_numbers(), 
+                        client_cursor_positions: doc.get_client_cursor_positions(), 
+                        document_cursor_position: doc.document_cursor_position(), 
+                        modified: doc.is_modified(), 
+                        matching_brackets: doc.get_client_matching_brackets()
+                    })
+                }else{
+                    Some(ServerResponse::CursorPosition{
+                        client_cursor_positions: doc.get_client_cursor_positions(), 
+                        document_cursor_position: doc.document_cursor_position(), 
+                        matching_brackets: doc.get_client_matching_brackets()
+                    })
+                }
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        },
         ServerAction::Save => {
-            if let Some(doc) = editor.document_mut(client_address){
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
                 match doc.save(){
                     Ok(_) => {
                         Some(ServerResponse::DisplayView{
@@ -487,15 +1288,99 @@ fn server_action_to_response(action: ServerAction, client_address: &str, editor:
                             line_numbers: doc.get_client_view_line_numbers(), 
                             client_cursor_positions: doc.get_client_cursor_positions(), 
                             document_cursor_position: doc.document_cursor_position(), 
-                            modified: doc.is_modified()
+                            modified: doc.is_modified(), 
+                            matching_brackets: doc.get_client_matching_brackets()
+                        })
+                    }
+                    Err(e) => {
+                        Some(ServerResponse::Failed(e))
+                    }
+                }
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::SetEncoding{encoding, bom} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                match doc.set_encoding(encoding, bom){
+                    Ok(_) => {
+                        Some(ServerResponse::DisplayView{
+                            content: doc.get_client_view_text(), 
+                            line_numbers: doc.get_client_view_line_numbers(), 
+                            client_cursor_positions: doc.get_client_cursor_positions(), 
+                            document_cursor_position: doc.document_cursor_position(), 
+                            modified: doc.is_modified(), 
+                            matching_brackets: doc.get_client_matching_brackets()
+                        })
+                    }
+                    Err(e) => {
+                        Some(ServerResponse::Failed(e))
+                    }
+                }
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::SetIndent{style, width} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.set_indent(style, width);
+                let _ = doc.scroll_view_following_cursor();
+                Some(ServerResponse::DisplayView{
+                    content: doc.get_client_view_text(), 
+                    line_numbers: doc.get_client_view_line_numbers(), 
+                    client_cursor_positions: doc.get_client_cursor_positions(), 
+                    document_cursor_position: doc.document_cursor_position(), 
+                    modified: doc.is_modified(), 
+                    matching_brackets: doc.get_client_matching_brackets()
+                })
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::SetAutoPairs{enabled} => {
+            if let Some(doc) = editor.document(session_token){
+                let mut doc = editor::lock(&doc);
+                doc.set_auto_pairs(enabled);
+                Some(ServerResponse::Acknowledge)
+            }else{
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::RecoverSwap => {
+            if let Some(doc) = editor.document(session_token){
+                match editor.recover_swap(session_token){
+                    Ok(_) => {
+                        let mut doc = editor::lock(&doc);
+                        let _ = doc.scroll_view_following_cursor();
+                        Some(ServerResponse::DisplayView{
+                            content: doc.get_client_view_text(), 
+                            line_numbers: doc.get_client_view_line_numbers(), 
+                            client_cursor_positions: doc.get_client_cursor_positions(), 
+                            document_cursor_position: doc.document_cursor_position(), 
+                            modified: doc.is_modified(), 
+                            matching_brackets: doc.get_client_matching_brackets()
                         })
                     }
                     Err(e) => {
-                        Some(ServerResponse::Failed(format!("failed to save. error: {}", e)))
+                        Some(ServerResponse::Failed(e))
                     }
                 }
             }else{
-                Some(ServerResponse::Failed("no document open".to_string()))
+                Some(ServerResponse::Failed(EditorError::NoDocumentOpen))
+            }
+        }
+        ServerAction::Shutdown{force, save} => {
+            match editor.prepare_shutdown(force, save){
+                Ok(_) => Some(ServerResponse::Acknowledge),
+                Err(e) => Some(ServerResponse::Failed(e)),
+            }
+        }
+        ServerAction::DiscardSwap => {
+            match editor.discard_swap(session_token){
+                Ok(_) => Some(ServerResponse::Acknowledge),
+                Err(e) => Some(ServerResponse::Failed(e)),
             }
         }
     }
@@ -1,7 +1,9 @@
 use crate::document;
 use crate::View;
 use crate::selection::Selection;
-use ropey::{Rope, RopeSlice};
+use ropey::RopeSlice;
+#[cfg(test)]
+use ropey::Rope;
 
 /// Sets rope cursor to a 0 based line number
 pub fn set_rope_cursor_position_from_line_number(mut selection: Selection, line_number: usize, text: RopeSlice) -> Selection{
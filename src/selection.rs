@@ -1,7 +1,8 @@
 use crate::Position;
+use serde::{Serialize, Deserialize};
 
 /// 1 dimensional representation of a single selection(between anchor and head) within a text rope. a cursor is a selection with a anchor/head difference of 0 or 1(depending on cursor semantics)
-#[derive(Default, PartialEq, Clone, Debug)]
+#[derive(Default, PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Selection{
     /// the stationary portion of a selection
     anchor: usize,
@@ -0,0 +1,65 @@
+use crate::error::EditorError;
+use crate::selection::Selection;
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+
+
+
+/// A named snapshot of a client's workspace, so it can be restored after the server restarts
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SessionFile{
+    pub documents: Vec<DocumentState>,
+    /// index into documents of the buffer the client was working in
+    pub focused: Option<usize>,
+}
+
+/// Where a client was in one document
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DocumentState{
+    pub file_path: PathBuf,
+    pub selections: Vec<Selection>,
+    /// the first visible line
+    pub vertical_start: usize,
+    /// the first visible column
+    pub horizontal_start: usize,
+}
+
+/// The location of the session file named name, under state_dir. names are plain file names, so they can not escape the sessions directory
+pub fn session_path(state_dir: &Path, name: &str) -> Result<PathBuf, EditorError>{
+    if name.is_empty() || name.starts_with('.') || name.contains(MAIN_SEPARATOR) || name.contains('/'){
+        return Err(EditorError::InvalidSessionName{name: name.to_string()});
+    }
+
+    Ok(state_dir.join("sessions").join(format!("{}.ron", name)))
+}
+
+pub fn write(state_dir: &Path, name: &str, session_file: &SessionFile) -> Result<(), EditorError>{
+    let session_path = session_path(state_dir, name)?;
+    if let Some(parent) = session_path.parent(){
+        fs::create_dir_all(parent).map_err(|e| EditorError::io(parent, e))?;
+    }
+    let serialized = ron::to_string(session_file)
+        .map_err(|e| EditorError::InvalidSessionFile{path: session_path.clone(), message: e.to_string()})?;
+    fs::write(&session_path, serialized).map_err(|e| EditorError::io(&session_path, e))
+}
+
+pub fn read(state_dir: &Path, name: &str) -> Result<SessionFile, EditorError>{
+    let session_path = session_path(state_dir, name)?;
+    let contents = fs::read_to_string(&session_path).map_err(|e| EditorError::io(&session_path, e))?;
+    ron::from_str(&contents).map_err(|e| EditorError::InvalidSessionFile{path: session_path, message: e.to_string()})
+}
+
+
+
+
+
+#[test]
+fn session_path_refuses_names_outside_sessions_directory(){
+    let state_dir = Path::new("state");
+    assert!(session_path(state_dir, "").is_err());
+    assert!(session_path(state_dir, "..").is_err());
+    assert!(session_path(state_dir, "../idk").is_err());
+    assert!(session_path(state_dir, "idk/something").is_err());
+    assert!(session_path(state_dir, "idk").unwrap() == state_dir.join("sessions").join("idk.ron"));
+}
@@ -0,0 +1,100 @@
+use crate::selection::Selection;
+use serde::{Serialize, Deserialize};
+use crate::error::EditorError;
+use std::fs;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::time::Duration;
+
+/// How often modified documents have their unsaved state persisted to a swap file
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+const APPLICATION_DIRECTORY_NAME: &str = "nlo_text_editor_server";
+
+
+
+/// Unsaved document state, persisted so edits can be recovered if the server dies before they are saved
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SwapFile{
+    pub text: String,
+    pub selections: Vec<Selection>,
+}
+
+/// The directory the server keeps its runtime state in. follows the xdg base directory spec, falling back to the system temp directory
+pub fn state_dir() -> PathBuf{
+    if let Some(state_home) = std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()){
+        PathBuf::from(state_home).join(APPLICATION_DIRECTORY_NAME)
+    }else if let Some(home) = std::env::var_os("HOME").filter(|dir| !dir.is_empty()){
+        PathBuf::from(home).join(".local").join("state").join(APPLICATION_DIRECTORY_NAME)
+    }else{
+        std::env::temp_dir().join(APPLICATION_DIRECTORY_NAME)
+    }
+}
+
+/// The location of the swap file, under state_dir, for the document at file_path
+pub fn swap_path(state_dir: &Path, file_path: &Path) -> PathBuf{
+    let absolute_path = fs::canonicalize(file_path).unwrap_or_else(|_| file_path.to_path_buf());
+    state_dir.join("swap").join(swap_file_name(&absolute_path))
+}
+
+// flattens a path into a single file name, the way vim names its swap files
+fn swap_file_name(absolute_path: &Path) -> String{
+    format!("{}.swp", absolute_path.to_string_lossy().replace(MAIN_SEPARATOR, "%"))
+}
+
+pub fn write(swap_path: &Path, swap_file: &SwapFile) -> Result<(), EditorError>{
+    if let Some(parent) = swap_path.parent(){
+        fs::create_dir_all(parent).map_err(|e| EditorError::io(parent, e))?;
+    }
+    let serialized = ron::to_string(swap_file)
+        .map_err(|e| EditorError::InvalidSwapFile{path: swap_path.to_path_buf(), message: e.to_string()})?;
+    // write then rename, so a crash mid write can not destroy the previous swap file
+    let temporary_path = swap_path.with_extension("swp.tmp");
+    fs::write(&temporary_path, serialized).map_err(|e| EditorError::io(&temporary_path, e))?;
+    fs::rename(&temporary_path, swap_path).map_err(|e| EditorError::io(swap_path, e))?;
+
+    Ok(())
+}
+
+pub fn read(swap_path: &Path) -> Result<SwapFile, EditorError>{
+    let contents = fs::read_to_string(swap_path).map_err(|e| EditorError::io(swap_path, e))?;
+    ron::from_str(&contents).map_err(|e| EditorError::InvalidSwapFile{path: swap_path.to_path_buf(), message: e.to_string()})
+}
+
+/// Removes the swap file at swap_path. a missing swap file is not an error
+pub fn remove(swap_path: &Path) -> Result<(), EditorError>{
+    match fs::remove_file(swap_path){
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(EditorError::io(swap_path, e)),
+    }
+}
+
+
+
+
+
+#[test]
+fn swap_file_name_flattens_path(){
+    let path = PathBuf::from(format!("{MAIN_SEPARATOR}home{MAIN_SEPARATOR}idk{MAIN_SEPARATOR}something.rs"));
+    assert!(swap_file_name(&path) == "%home%idk%something.rs.swp");
+}
+#[test]
+fn swap_file_round_trips(){
+    let swap_path = std::env::temp_dir().join(format!("nlo_swap_round_trip_{}.swp", std::process::id()));
+    let swap_file = SwapFile{text: "idk\nsomething\n".to_string(), selections: vec![Selection::new(2, 5, 1)]};
+    write(&swap_path, &swap_file).unwrap();
+    assert!(read(&swap_path).unwrap() == swap_file);
+    remove(&swap_path).unwrap();
+    assert!(!swap_path.exists());
+}
+#[test]
+fn remove_missing_swap_file_is_not_an_error(){
+    let swap_path = std::env::temp_dir().join(format!("nlo_swap_missing_{}.swp", std::process::id()));
+    assert!(remove(&swap_path).is_ok());
+}
+#[test]
+fn swap_path_is_under_state_dir(){
+    let state_dir = PathBuf::from(format!("{MAIN_SEPARATOR}state"));
+    let file_path = PathBuf::from(format!("{MAIN_SEPARATOR}idk{MAIN_SEPARATOR}something.rs"));
+    assert!(swap_path(&state_dir, &file_path) == state_dir.join("swap").join("%idk%something.rs.swp"));
+}